@@ -50,15 +50,16 @@ fn main() {
         block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).unwrap();
 
     // Set up swap chain
-    let surface_desc = wgpu::SurfaceConfiguration {
+    let surface_config = |width, height| wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: wgpu::TextureFormat::Bgra8UnormSrgb,
-        width: size.width,
-        height: size.height,
-        present_mode: wgpu::PresentMode::Fifo,
+        width,
+        height,
+        present_mode: wgpu::PresentMode::AutoVsync,
         alpha_mode: wgpu::CompositeAlphaMode::Auto,
         view_formats: vec![wgpu::TextureFormat::Bgra8Unorm],
     };
+    let surface_desc = surface_config(size.width, size.height);
 
     surface.configure(&device, &surface_desc);
 
@@ -107,6 +108,9 @@ fn main() {
     };
 
     let mut renderer = Renderer::new(&mut imgui, &device, &queue, &window, renderer_config);
+    renderer
+        .set_surface_capabilities(surface.get_capabilities(&adapter))
+        .expect("Unsupported surface");
 
     let mut last_frame = Instant::now();
     let mut demo_open = true;
@@ -130,17 +134,10 @@ fn main() {
             } if window_id == window.id() => {
                 let size = window.inner_size();
 
-                let surface_desc = wgpu::SurfaceConfiguration {
-                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                    format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                    width: size.width,
-                    height: size.height,
-                    present_mode: wgpu::PresentMode::Fifo,
-                    alpha_mode: wgpu::CompositeAlphaMode::Auto,
-                    view_formats: vec![wgpu::TextureFormat::Bgra8Unorm],
-                };
-
-                surface.configure(&device, &surface_desc);
+                // Minimizing reports a zero size, which no surface can be configured with.
+                if size.width != 0 && size.height != 0 {
+                    surface.configure(&device, &surface_config(size.width, size.height));
+                }
             }
             Event::WindowEvent {
                 event:
@@ -165,16 +162,9 @@ fn main() {
             Event::RedrawEventsCleared => {
                 let delta_s = last_frame.elapsed();
                 let now = Instant::now();
-                imgui.io_mut().update_delta_time(now - last_frame);
+                renderer.prepare_frame(&mut imgui, &window, now - last_frame);
                 last_frame = now;
 
-                let frame = match surface.get_current_texture() {
-                    Ok(frame) => frame,
-                    Err(e) => {
-                        eprintln!("dropped frame: {e:?}");
-                        return;
-                    }
-                };
                 // platform
                 //     .prepare_frame(imgui.io_mut(), &window)
                 //     .expect("Failed to prepare frame");
@@ -211,54 +201,17 @@ fn main() {
                     ui.show_demo_window(&mut demo_open);
                 }
 
-                let mut encoder: wgpu::CommandEncoder =
-                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-
-                // if last_cursor != Some(ui.mouse_cursor()) {
-                //     last_cursor = Some(ui.mouse_cursor());
-                //     // platform.prepare_render(ui, &window);
-                // }
-
-                let view = frame
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default());
-                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                    label: None,
-                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(clear_color),
-                            store: true,
-                        },
-                    })],
-                    depth_stencil_attachment: None,
-                });
-
-                ui.end_frame_early();
-
-                renderer.prepare_render(&mut imgui, &window);
-
-                imgui.update_platform_windows();
                 renderer
-                    .update_viewports(&mut imgui, &e_loop, &device, &instance)
+                    .update(&mut imgui, &window, e_loop, &device, &queue, &instance)
                     .expect("Failed to update viewports.");
 
                 renderer
-                    .render(&mut imgui, &queue, &device, &mut rpass)
+                    .render_main(&mut imgui, &device, &queue, &surface, clear_color)
                     .expect("Rendering failed");
-                // renderer
-                //     .render(imgui.render(), &queue, &device, &mut rpass)
-                //     .expect("Rendering failed");
-
-                drop(rpass);
-
-                queue.submit(Some(encoder.finish()));
 
-                let format = frame.texture.format();
-                frame.present();
-
-                renderer.render_viewports(&mut imgui, &device, &queue, format);
+                if let Err(e) = renderer.render_viewports(&mut imgui, &device, &queue) {
+                    eprintln!("{e}");
+                }
             }
             _ => (),
         }
@@ -0,0 +1,122 @@
+//! Gamepad navigation through gilrs, enabled by the `gilrs` feature. winit doesn't
+//! report gamepads itself.
+
+use gilrs::{Axis, Button, Gilrs};
+use imgui::{internal::RawCast, BackendFlags, ConfigFlags, Io, Key};
+
+// Stick travel below this is treated as resting, worn sticks rarely report a clean 0.
+const STICK_DEAD_ZONE: f32 = 0.2;
+
+const BUTTONS: [(Button, Key); 14] = [
+    (Button::Start, Key::GamepadStart),
+    (Button::Select, Key::GamepadBack),
+    (Button::West, Key::GamepadFaceLeft),
+    (Button::East, Key::GamepadFaceRight),
+    (Button::North, Key::GamepadFaceUp),
+    (Button::South, Key::GamepadFaceDown),
+    (Button::DPadLeft, Key::GamepadDpadLeft),
+    (Button::DPadRight, Key::GamepadDpadRight),
+    (Button::DPadUp, Key::GamepadDpadUp),
+    (Button::DPadDown, Key::GamepadDpadDown),
+    (Button::LeftTrigger, Key::GamepadL1),
+    (Button::RightTrigger, Key::GamepadR1),
+    (Button::LeftThumb, Key::GamepadL3),
+    (Button::RightThumb, Key::GamepadR3),
+];
+
+/// Feeds the state of all connected gamepads into imgui's gamepad keys.
+pub struct GamepadInput {
+    gilrs: Gilrs,
+}
+
+impl GamepadInput {
+    /// Also turns on imgui's gamepad navigation.
+    // The error is gilrs' own, which is this large already.
+    #[allow(clippy::result_large_err)]
+    pub fn new(io: &mut Io) -> Result<Self, gilrs::Error> {
+        io.config_flags.insert(ConfigFlags::NAV_ENABLE_GAMEPAD);
+
+        Ok(Self {
+            gilrs: Gilrs::new()?,
+        })
+    }
+
+    /// Call once per frame before `imgui.frame()`. With several gamepads connected the
+    /// strongest input of any of them wins.
+    pub fn update(&mut self, io: &mut Io) {
+        // Only gilrs' cached state is read below, the events just keep it current.
+        while self.gilrs.next_event().is_some() {}
+
+        let mut connected = false;
+        let mut buttons = [false; BUTTONS.len()];
+        let mut triggers = [0.0f32; 2];
+        let mut sticks = [0.0f32; 4];
+        for (_, gamepad) in self.gilrs.gamepads() {
+            connected = true;
+
+            for (down, (button, _)) in buttons.iter_mut().zip(BUTTONS) {
+                *down |= gamepad.is_pressed(button);
+            }
+
+            for (value, button) in triggers
+                .iter_mut()
+                .zip([Button::LeftTrigger2, Button::RightTrigger2])
+            {
+                let pressure = gamepad.button_data(button).map_or(0.0, |data| data.value());
+                *value = value.max(pressure);
+            }
+
+            for (value, axis) in sticks.iter_mut().zip([
+                Axis::LeftStickX,
+                Axis::LeftStickY,
+                Axis::RightStickX,
+                Axis::RightStickY,
+            ]) {
+                let travel = gamepad.value(axis);
+                if travel.abs() > value.abs() {
+                    *value = travel;
+                }
+            }
+        }
+
+        // imgui ignores the gamepad keys while no gamepad is flagged.
+        io.backend_flags.set(BackendFlags::HAS_GAMEPAD, connected);
+        if !connected {
+            return;
+        }
+
+        for (down, (_, key)) in buttons.into_iter().zip(BUTTONS) {
+            io.add_key_event(key, down);
+        }
+
+        for (value, key) in triggers.into_iter().zip([Key::GamepadL2, Key::GamepadR2]) {
+            add_analog(io, key, value);
+        }
+
+        // gilrs reports up as positive, imgui splits every direction into its own key.
+        let [lx, ly, rx, ry] = sticks;
+        for (value, key) in [
+            (-lx, Key::GamepadLStickLeft),
+            (lx, Key::GamepadLStickRight),
+            (ly, Key::GamepadLStickUp),
+            (-ly, Key::GamepadLStickDown),
+            (-rx, Key::GamepadRStickLeft),
+            (rx, Key::GamepadRStickRight),
+            (ry, Key::GamepadRStickUp),
+            (-ry, Key::GamepadRStickDown),
+        ] {
+            add_analog(
+                io,
+                key,
+                ((value - STICK_DEAD_ZONE) / (1.0 - STICK_DEAD_ZONE)).clamp(0.0, 1.0),
+            );
+        }
+    }
+}
+
+// imgui-rs has no wrapper for analog key events.
+fn add_analog(io: &mut Io, key: Key, value: f32) {
+    unsafe {
+        imgui::sys::ImGuiIO_AddKeyAnalogEvent(io.raw_mut(), key as u32, value > 0.1, value);
+    }
+}
@@ -1,35 +1,268 @@
 use std::{
     cell::RefCell,
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
     ptr::null_mut,
     rc::Rc,
+    sync::Arc,
+    time::Duration,
 };
 
-use imgui::{ConfigFlags, Id, Key, MouseButton, ViewportFlags};
+// Diagnostics go to the `log` crate when the `log` feature is enabled and are
+// discarded otherwise.
+macro_rules! diagnostic {
+    ($level:ident, $($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+mod blit;
+#[cfg(feature = "gilrs")]
+pub mod gamepad;
+#[cfg(feature = "winit_0_29")]
+pub mod winit29;
+
+use imgui::{internal::RawCast, ConfigFlags, Id, Key, MouseButton, TextureId, ViewportFlags};
 pub use imgui_wgpu::RendererConfig;
 
-use imgui_wgpu::{Renderer as SRenderer, RendererError};
+use blit::Blitter;
+use imgui_wgpu::{RawTextureConfig, Renderer as SRenderer, RendererError, Texture};
 use raw_window_handle::HasRawWindowHandle;
 use wgpu::{Surface, TextureFormat};
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
     event::{DeviceEvent, ElementState, KeyboardInput, TouchPhase, VirtualKeyCode},
     event_loop::EventLoopWindowTarget,
-    window::{CursorIcon, WindowBuilder},
+    window::{CursorIcon, Fullscreen, Icon, Theme, WindowBuilder, WindowId},
+};
+
+pub const DEFAULT_PIXELS_PER_LINE: f32 = 20.0;
+
+const VIEWPORT_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.0,
+    g: 0.0,
+    b: 0.0,
+    a: 1.0,
 };
 
+// The mouse position imgui takes as "no mouse", for a cursor outside every window.
+const NO_MOUSE_POS: [f32; 2] = [-f32::MAX, -f32::MAX];
+
+type ViewportCreatedCallback = dyn FnMut(Id, &winit::window::Window);
+type WindowBuilderHook = dyn Fn(&imgui::Viewport, WindowBuilder) -> WindowBuilder;
+type PresentModePolicy = dyn Fn(&imgui::Viewport) -> wgpu::PresentMode;
+
 pub struct Renderer {
     main_renderer: SRenderer,
-    extra_windows: HashMap<Id, (Option<SRenderer>, Surface, winit::window::Window)>,
+    extra_windows: HashMap<Id, ViewportWindow>,
     event_queue: Rc<RefCell<VecDeque<ViewportEvent>>>,
-    last_cursor: CursorIcon,
+    last_cursor: Option<CursorIcon>,
+    cursor_override: Option<CursorIcon>,
+    cursor_hidden: bool,
+    sample_count: u32,
+    viewport_icons: HashMap<Id, Icon>,
+    viewport_titles: HashMap<Id, String>,
+    viewport_size_limits: HashMap<Id, ViewportSizeLimits>,
+    viewport_resize_increments: HashMap<Id, [u32; 2]>,
+    viewport_window_states: HashMap<Id, ViewportWindowState>,
+    restored_placements: HashMap<Id, ViewportPlacement>,
+    requested_sizes: HashMap<Id, [u32; 2]>,
+    main_surface: Option<Surface>,
+    main_sample_count: u32,
+    main_texture_format: TextureFormat,
+    offscreen_targets: RenderTargets,
+    main_targets: RenderTargets,
+    main_minimized: bool,
+    occluded_windows: HashSet<WindowId>,
+    physical_keys: bool,
+    left_alt_down: bool,
+    pixels_per_line: f32,
+    monitors: Vec<MonitorInfo>,
+    focused_window: Option<WindowId>,
+    hovered_window: Option<WindowId>,
+    app_focused: bool,
+    supersample: f32,
+    blitters: HashMap<TextureFormat, Blitter>,
+    manage_cursor: bool,
+    supports_viewports: bool,
+    warned_unsupported_viewports: bool,
+    transparent_viewports: HashSet<Id>,
+    transparent_all_viewports: bool,
+    viewport_alpha_mode: wgpu::CompositeAlphaMode,
+    viewport_format: TextureFormat,
+    viewport_view_format: TextureFormat,
+    viewport_clear_color: wgpu::Color,
+    viewport_clear_color_srgb: bool,
+    present_mode: wgpu::PresentMode,
+    viewport_depth_format: Option<TextureFormat>,
+    surface_capabilities: Option<wgpu::SurfaceCapabilities>,
+    textures: HashMap<TextureId, UserTexture>,
+    font_texture: UserTexture,
+    close_requests: Vec<Id>,
+    dropped_files: Vec<(Id, PathBuf)>,
+    hovered_files: Vec<(Id, PathBuf)>,
+    defer_close_requests: bool,
+    // Frames acquired this frame; the list is kept on the renderer and cleared after presenting.
+    frames: Vec<(Id, wgpu::SurfaceTexture)>,
+    coalesce_cursor_moves: bool,
+    pending_mouse_pos: Option<[f32; 2]>,
+    active_touch: Option<u64>,
+    platform_name: String,
+    renderer_name: String,
+    on_present: Option<Box<dyn FnMut(Id)>>,
+    on_viewport_created: Option<Box<ViewportCreatedCallback>>,
+    on_viewport_destroyed: Option<Box<dyn FnMut(Id)>>,
+    theme: Option<Theme>,
+    follow_theme: bool,
+    on_theme_changed: Option<Box<dyn FnMut(Theme)>>,
+    window_builder: Option<Box<WindowBuilderHook>>,
+    viewport_renderer_config: Option<Box<dyn Fn() -> RendererConfig<'static>>>,
+    present_mode_policy: Option<Box<PresentModePolicy>>,
+    // Created ahead of time by `warm_up` for the next viewport window.
+    spare_renderer: Option<SRenderer>,
+    main_work_area_insets: [f32; 4],
+    main_viewport_id: Id,
+    main_window_id: WindowId,
+    main_scale_factor: f64,
+}
+
+struct UserTexture {
+    texture: Arc<wgpu::Texture>,
+    view: Arc<wgpu::TextureView>,
+    size: wgpu::Extent3d,
+    sampler: Option<wgpu::SamplerDescriptor<'static>>,
+}
+
+struct MonitorInfo {
+    pos: [f32; 2],
+    size: [f32; 2],
+    refresh_rate_millihertz: Option<u32>,
+}
+
+// Fields drop in declaration order. The surface was created from the raw handle of
+// `window` and must be gone before the window is, so keep `surface` above `window`.
+struct ViewportWindow {
+    renderer: Option<SRenderer>,
+    targets: RenderTargets,
+    surface: Surface,
+    window: winit::window::Window,
+    show_pending: bool,
+    /// Picked by the present mode policy, `None` follows `Renderer::present_mode`.
+    present_mode: Option<wgpu::PresentMode>,
+    /// The size the surface was last configured with.
+    surface_size: [u32; 2],
+}
+
+impl ViewportWindow {
+    fn configure_surface(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.surface.configure(device, config);
+        self.surface_size = [config.width, config.height];
+    }
+}
+
+#[derive(Default)]
+struct RenderTargets {
+    format: Option<TextureFormat>,
+    msaa: Option<(wgpu::TextureView, [u32; 2])>,
+    supersample: Option<(wgpu::TextureView, [u32; 2])>,
+    depth: Option<(wgpu::TextureView, [u32; 2])>,
+    blit_bind_group: Option<wgpu::BindGroup>,
+}
+
+/// Where the window of a viewport sits on screen, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportPlacement {
+    pub pos: [f32; 2],
+    pub size: [f32; 2],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViewportWindowState {
+    #[default]
+    Normal,
+    Maximized,
+    /// Borderless fullscreen on the monitor the window is on.
+    Fullscreen,
+}
+
+/// How much imgui drew into a viewport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DrawStats {
+    pub vertices: u32,
+    pub indices: u32,
+    pub draw_calls: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RenderStats {
+    pub total: DrawStats,
+    /// The main viewport first, then every extra window.
+    pub viewports: Vec<(Id, DrawStats)>,
+}
+
+/// Bounds on the inner size of a viewport window, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ViewportSizeLimits {
+    pub min: Option<[u32; 2]>,
+    pub max: Option<[u32; 2]>,
+}
+
+#[derive(Debug)]
+pub enum ViewportRenderError {
+    Renderer(Id, RendererError),
+    Surface(Id, wgpu::SurfaceError),
+}
+
+impl std::fmt::Display for ViewportRenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ViewportRenderError::Renderer(id, e) => {
+                write!(f, "Failed to render viewport {id:?}: {e:?}")
+            }
+            ViewportRenderError::Surface(id, e) => {
+                write!(f, "Failed to acquire frame for viewport {id:?}: {e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ViewportRenderError {}
+
+#[derive(Debug)]
+pub enum CreateRendererError {
+    Device(wgpu::Error),
+    UnsupportedFormat(TextureFormat),
+    UnsupportedAlphaMode(wgpu::CompositeAlphaMode),
+}
+
+impl std::fmt::Display for CreateRendererError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CreateRendererError::Device(e) => write!(
+                f,
+                "Failed to create the imgui renderer, the adapter may lack features imgui-wgpu needs: {e}"
+            ),
+            CreateRendererError::UnsupportedFormat(format) => {
+                write!(f, "The surface does not support the texture format {format:?}")
+            }
+            CreateRendererError::UnsupportedAlphaMode(mode) => {
+                write!(f, "The surface does not support the alpha mode {mode:?}")
+            }
+        }
+    }
 }
 
+impl std::error::Error for CreateRendererError {}
+
 struct ViewportData {
     pos: [f32; 2],
     size: [f32; 2],
     focus: bool,
     minimized: bool,
+    top_most: bool,
 }
 #[derive(Debug)]
 enum ViewportEvent {
@@ -40,6 +273,9 @@ enum ViewportEvent {
     SetVisible(Id),
     SetFocus(Id),
     SetTitle(Id, String),
+    SetTopMost(Id, bool),
+    SetWindowState(Id, ViewportWindowState),
+    SetSizeLimits(Id, ViewportSizeLimits),
 }
 
 struct PlatformBackend {
@@ -47,6 +283,12 @@ struct PlatformBackend {
 }
 
 impl Renderer {
+    /// Installs the platform and renderer backends into `imgui`. Every context needs
+    /// its own `Renderer`: the backends and their viewport event queue belong to the
+    /// context they were installed into, so two renderers never see each other's
+    /// viewports. imgui-rs only keeps one context active at a time, so suspend the
+    /// other one (`Context::suspend`) around each renderer's calls. Both renderers may
+    /// share the same main window and get all of its events passed in.
     pub fn new(
         imgui: &mut imgui::Context,
         device: &wgpu::Device,
@@ -54,22 +296,61 @@ impl Renderer {
         main_window: &winit::window::Window,
         renderer_config: RendererConfig,
     ) -> Self {
-        let main_renderer = SRenderer::new(imgui, device, queue, renderer_config);
+        Self::try_new(imgui, device, queue, main_window, renderer_config)
+            .expect("Failed to create renderer")
+    }
+
+    pub fn try_new(
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        main_window: &winit::window::Window,
+        renderer_config: RendererConfig,
+    ) -> Result<Self, CreateRendererError> {
+        let main_sample_count = renderer_config.sample_count;
+        let main_texture_format = renderer_config.texture_format;
 
-        match main_window.raw_window_handle() {
-            raw_window_handle::RawWindowHandle::Wayland(_) => {}
-            _ => {
-                imgui
-                    .io_mut()
-                    .backend_flags
-                    .insert(imgui::BackendFlags::PLATFORM_HAS_VIEWPORTS);
-                imgui
-                    .io_mut()
-                    .backend_flags
-                    .insert(imgui::BackendFlags::RENDERER_HAS_VIEWPORTS);
+        // Pipeline creation failures are reported through the device's error handler,
+        // which panics by default. Capture them instead. On the web the error scope
+        // resolves through a JS promise, which can't be blocked on.
+        let capture_errors = !cfg!(target_arch = "wasm32");
+        if capture_errors {
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+        }
+        let mut main_renderer = SRenderer::new(imgui, device, queue, renderer_config);
+        if capture_errors {
+            if let Some(e) = pollster::block_on(device.pop_error_scope()) {
+                return Err(CreateRendererError::Device(e));
             }
         }
 
+        let font_texture = upload_font_atlas(imgui, device, queue);
+        register_user_texture(
+            device,
+            &mut main_renderer,
+            imgui.fonts().tex_id,
+            &font_texture,
+        );
+
+        // Wayland does not let clients position their own windows, which viewports rely on.
+        // The web only has the canvas the main window renders to.
+        let supports_viewports = !cfg!(target_arch = "wasm32")
+            && !matches!(
+                main_window.raw_window_handle(),
+                raw_window_handle::RawWindowHandle::Wayland(_)
+            );
+
+        if supports_viewports {
+            imgui
+                .io_mut()
+                .backend_flags
+                .insert(imgui::BackendFlags::PLATFORM_HAS_VIEWPORTS);
+            imgui
+                .io_mut()
+                .backend_flags
+                .insert(imgui::BackendFlags::RENDERER_HAS_VIEWPORTS);
+        }
+
         imgui
             .io_mut()
             .backend_flags
@@ -87,8 +368,12 @@ impl Renderer {
         let window_size = main_window.inner_size().cast::<f32>();
         imgui.io_mut().display_size = [window_size.width, window_size.height];
         imgui.io_mut().display_framebuffer_scale = [1.0, 1.0];
+        // winit can't query the cursor position, so report "no mouse" until the first
+        // CursorMoved instead of letting imgui hover whatever sits at (0, 0).
+        imgui.io_mut().add_mouse_pos_event(NO_MOUSE_POS);
 
         let viewport = imgui.main_viewport_mut();
+        let main_viewport_id = viewport.id;
 
         let main_pos = main_window
             .inner_position()
@@ -96,42 +381,28 @@ impl Renderer {
             .cast::<f32>();
 
         viewport.pos = [main_pos.x, main_pos.y];
-        viewport.work_pos = viewport.pos;
         viewport.size = [window_size.width, window_size.height];
-        viewport.work_size = viewport.size;
-        viewport.dpi_scale = 1.0;
+        (viewport.work_pos, viewport.work_size) = work_area(viewport.pos, viewport.size, [0.0; 4]);
+        viewport.dpi_scale = main_window.scale_factor() as f32;
         viewport.platform_user_data = Box::into_raw(Box::new(ViewportData {
             pos: [main_pos.x, main_pos.y],
             size: [window_size.width, window_size.height],
             focus: true,
             minimized: false,
+            top_most: false,
         }))
         .cast();
 
-        let mut monitors = Vec::new();
-        for monitor in main_window.available_monitors() {
-            monitors.push(imgui::PlatformMonitor {
-                main_pos: [monitor.position().x as f32, monitor.position().y as f32],
-                main_size: [monitor.size().width as f32, monitor.size().height as f32],
-                work_pos: [monitor.position().x as f32, monitor.position().y as f32],
-                work_size: [monitor.size().width as f32, monitor.size().height as f32],
-                dpi_scale: 1.0,
-            });
-        }
+        let (monitors, monitor_infos) = collect_monitors(main_window);
 
         imgui
             .platform_io_mut()
             .monitors
             .replace_from_slice(&monitors);
 
-        imgui.set_platform_name(Some(format!(
-            "imgui-winit-wgpu-renderer-viewports {}",
-            env!("CARGO_PKG_VERSION")
-        )));
-        imgui.set_renderer_name(Some(format!(
-            "imgui-winit-wgpu-renderer-viewports {}",
-            env!("CARGO_PKG_VERSION")
-        )));
+        let (platform_name, renderer_name) = backend_names();
+        imgui.set_platform_name(Some(platform_name.clone()));
+        imgui.set_renderer_name(Some(renderer_name.clone()));
 
         let event_queue = Rc::new(RefCell::new(VecDeque::new()));
 
@@ -140,37 +411,196 @@ impl Renderer {
         });
         imgui.set_renderer_backend(RendererBackend {});
 
-        Self {
+        Ok(Self {
             main_renderer,
             event_queue,
             extra_windows: HashMap::new(),
-            last_cursor: CursorIcon::Default,
-        }
+            last_cursor: None,
+            cursor_override: None,
+            cursor_hidden: false,
+            sample_count: 1,
+            viewport_icons: HashMap::new(),
+            viewport_titles: HashMap::new(),
+            viewport_size_limits: HashMap::new(),
+            viewport_resize_increments: HashMap::new(),
+            viewport_window_states: HashMap::new(),
+            restored_placements: HashMap::new(),
+            requested_sizes: HashMap::new(),
+            main_surface: None,
+            main_sample_count,
+            main_texture_format,
+            offscreen_targets: RenderTargets::default(),
+            main_targets: RenderTargets::default(),
+            main_minimized: false,
+            occluded_windows: HashSet::new(),
+            physical_keys: false,
+            left_alt_down: false,
+            pixels_per_line: DEFAULT_PIXELS_PER_LINE,
+            monitors: monitor_infos,
+            focused_window: Some(main_window.id()),
+            hovered_window: None,
+            app_focused: true,
+            supersample: 1.0,
+            blitters: HashMap::new(),
+            manage_cursor: true,
+            supports_viewports,
+            warned_unsupported_viewports: false,
+            transparent_viewports: HashSet::new(),
+            transparent_all_viewports: false,
+            viewport_alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            viewport_format: TextureFormat::Bgra8UnormSrgb,
+            viewport_view_format: TextureFormat::Bgra8Unorm,
+            viewport_clear_color: VIEWPORT_CLEAR_COLOR,
+            viewport_clear_color_srgb: false,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            viewport_depth_format: None,
+            surface_capabilities: None,
+            textures: HashMap::new(),
+            font_texture,
+            close_requests: Vec::new(),
+            dropped_files: Vec::new(),
+            hovered_files: Vec::new(),
+            defer_close_requests: false,
+            frames: Vec::new(),
+            coalesce_cursor_moves: false,
+            pending_mouse_pos: None,
+            active_touch: None,
+            platform_name,
+            renderer_name,
+            on_present: None,
+            on_viewport_created: None,
+            on_viewport_destroyed: None,
+            theme: None,
+            follow_theme: false,
+            on_theme_changed: None,
+            window_builder: None,
+            viewport_renderer_config: None,
+            present_mode_policy: None,
+            spare_renderer: None,
+            main_work_area_insets: [0.0; 4],
+            main_viewport_id,
+            main_window_id: main_window.id(),
+            main_scale_factor: main_window.scale_factor(),
+        })
     }
 
+    /// Feeds `event` to imgui. Returns true for mouse and keyboard input imgui wants
+    /// to capture, which the application should then not handle itself.
+    ///
+    /// Focus changes and coalesced cursor moves are held back until the batch of events
+    /// is complete. They reach imgui when `MainEventsCleared` is passed in here, or at
+    /// the latest in `prepare_frame`.
     pub fn handle_event<T>(
         &mut self,
         imgui: &mut imgui::Context,
         main_window: &winit::window::Window,
         device: &wgpu::Device,
         event: &winit::event::Event<T>,
+    ) -> bool {
+        self.process_event(imgui, main_window, device, event);
+
+        match event {
+            winit::event::Event::WindowEvent { event, .. } => match event {
+                winit::event::WindowEvent::CursorMoved { .. }
+                | winit::event::WindowEvent::MouseInput { .. }
+                | winit::event::WindowEvent::MouseWheel { .. }
+                | winit::event::WindowEvent::Touch(_) => self.wants_mouse(imgui),
+                winit::event::WindowEvent::KeyboardInput { .. }
+                | winit::event::WindowEvent::ModifiersChanged(_) => self.wants_keyboard(imgui),
+                winit::event::WindowEvent::ReceivedCharacter(_) => {
+                    self.wants_keyboard(imgui) || imgui.io().want_text_input
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether imgui uses the mouse, e.g. because it hovers an imgui window. As of
+    /// the last frame.
+    pub fn wants_mouse(&self, imgui: &imgui::Context) -> bool {
+        imgui.io().want_capture_mouse
+    }
+
+    /// Whether imgui uses the keyboard, e.g. because a text field is active. As of the
+    /// last frame.
+    pub fn wants_keyboard(&self, imgui: &imgui::Context) -> bool {
+        imgui.io().want_capture_keyboard
+    }
+
+    /// Points the renderer at a recreated main window. `handle_event` does this by itself
+    /// when it is passed a different main window than before.
+    pub fn set_main_window(
+        &mut self,
+        imgui: &mut imgui::Context,
+        main_window: &winit::window::Window,
+    ) {
+        let old_id = std::mem::replace(&mut self.main_window_id, main_window.id());
+        self.occluded_windows.remove(&old_id);
+        if self.focused_window == Some(old_id) {
+            self.focused_window = Some(main_window.id());
+        }
+        self.main_scale_factor = main_window.scale_factor();
+        // Makes `prepare_render` apply the cursor to the new window.
+        self.last_cursor = None;
+
+        let size = main_window.inner_size();
+        self.main_minimized = size.width == 0 || size.height == 0;
+        let size = size.cast::<f32>();
+        let pos = main_window
+            .inner_position()
+            .unwrap_or_default()
+            .cast::<f32>();
+
+        if !self.main_minimized {
+            imgui.io_mut().display_size = [size.width, size.height];
+        }
+
+        let viewport = imgui.main_viewport_mut();
+        viewport.pos = [pos.x, pos.y];
+        viewport.size = [size.width, size.height];
+        (viewport.work_pos, viewport.work_size) =
+            work_area(viewport.pos, viewport.size, self.main_work_area_insets);
+        viewport.dpi_scale = self.main_scale_factor as f32;
+
+        let data = unsafe { viewport.platform_user_data.cast::<ViewportData>().as_mut() };
+        if let Some(data) = data {
+            data.pos = viewport.pos;
+            data.size = viewport.size;
+            data.minimized = self.main_minimized;
+        }
+    }
+
+    fn process_event<T>(
+        &mut self,
+        imgui: &mut imgui::Context,
+        main_window: &winit::window::Window,
+        device: &wgpu::Device,
+        event: &winit::event::Event<T>,
     ) {
+        if main_window.id() != self.main_window_id {
+            self.set_main_window(imgui, main_window);
+        }
+
         match *event {
             winit::event::Event::WindowEvent {
                 window_id,
                 ref event,
             } => {
+                let viewports_enabled = imgui
+                    .io()
+                    .config_flags
+                    .contains(ConfigFlags::VIEWPORTS_ENABLE);
+
                 let (window, viewport) = if window_id == main_window.id() {
                     (main_window, imgui.main_viewport_mut())
-                } else if let Some((id, wnd)) =
-                    self.extra_windows.iter().find_map(|(id, (_, _, wnd))| {
-                        if wnd.id() == window_id {
-                            Some((*id, wnd))
-                        } else {
-                            None
-                        }
-                    })
-                {
+                } else if let Some((id, wnd)) = self.extra_windows.iter().find_map(|(id, extra)| {
+                    if extra.window.id() == window_id {
+                        Some((*id, &extra.window))
+                    } else {
+                        None
+                    }
+                }) {
                     if let Some(viewport) = imgui.viewport_by_id_mut(id) {
                         (wnd, viewport)
                     } else {
@@ -182,51 +612,157 @@ impl Renderer {
 
                 match *event {
                     winit::event::WindowEvent::Resized(new_size) => {
+                        // Minimizing reports a zero size. Keep the last known size so imgui
+                        // never lays out against an empty display and wgpu never sees a
+                        // zero sized surface.
+                        let minimized = new_size.width == 0 || new_size.height == 0;
                         unsafe {
-                            (*(viewport.platform_user_data.cast::<ViewportData>())).size =
-                                [new_size.width as f32, new_size.height as f32];
+                            (*(viewport.platform_user_data.cast::<ViewportData>())).minimized =
+                                minimized;
                         }
 
-                        viewport.platform_request_resize = true;
-
                         if window_id == main_window.id() {
-                            imgui.io_mut().display_size =
-                                [new_size.width as f32, new_size.height as f32];
-                        } else {
-                            let surface_desc = wgpu::SurfaceConfiguration {
-                                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                                format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                                width: window.inner_size().width,
-                                height: window.inner_size().height,
-                                present_mode: wgpu::PresentMode::Fifo,
-                                alpha_mode: wgpu::CompositeAlphaMode::Auto,
-                                view_formats: vec![wgpu::TextureFormat::Bgra8Unorm],
-                            };
-                            let (_, surface, _) = self.extra_windows.get(&viewport.id).unwrap();
+                            self.main_minimized = minimized;
+                        }
+
+                        if !minimized {
+                            unsafe {
+                                (*(viewport.platform_user_data.cast::<ViewportData>())).size =
+                                    [new_size.width as f32, new_size.height as f32];
+                            }
+
+                            if window_id == main_window.id() {
+                                (viewport.work_pos, viewport.work_size) = work_area(
+                                    viewport.pos,
+                                    [new_size.width as f32, new_size.height as f32],
+                                    self.main_work_area_insets,
+                                );
+                            }
+
+                            // The echo of a size imgui asked for itself is not a request,
+                            // answering it could start a resize feedback loop.
+                            let requested = self.requested_sizes.remove(&viewport.id)
+                                == Some([new_size.width, new_size.height]);
+                            if !requested {
+                                viewport.platform_request_resize = true;
+                            }
+
+                            if window_id == main_window.id() {
+                                apply_display_size(imgui.io_mut(), new_size);
+
+                                if let Some(surface) = &self.main_surface {
+                                    let surface_desc = wgpu::SurfaceConfiguration {
+                                        format: self.main_texture_format,
+                                        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+                                        view_formats: Vec::new(),
+                                        ..self.make_surface_config(new_size.width, new_size.height)
+                                    };
+
+                                    surface.configure(device, &surface_desc);
+                                }
+                            } else {
+                                let surface_desc = self.viewport_surface_config(
+                                    &self.extra_windows[&viewport.id],
+                                    new_size.width,
+                                    new_size.height,
+                                );
 
-                            surface.configure(device, &surface_desc);
+                                self.extra_windows
+                                    .get_mut(&viewport.id)
+                                    .unwrap()
+                                    .configure_surface(device, &surface_desc);
+                            }
                         }
                     }
-                    winit::event::WindowEvent::Moved(_) => unsafe {
-                        let new_pos = window.inner_position().unwrap().cast::<f32>();
-                        (*(viewport.platform_user_data.cast::<ViewportData>())).pos =
-                            [new_pos.x, new_pos.y];
+                    // Without viewports imgui never asks where the main window is.
+                    winit::event::WindowEvent::Moved(_)
+                        if window_id != main_window.id() || viewports_enabled =>
+                    {
+                        if let Ok(new_pos) = window.inner_position() {
+                            let new_pos = new_pos.cast::<f32>();
+                            unsafe {
+                                (*(viewport.platform_user_data.cast::<ViewportData>())).pos =
+                                    [new_pos.x, new_pos.y];
+                            }
 
-                        viewport.platform_request_move = true;
-                    },
-                    winit::event::WindowEvent::CloseRequested if window_id != main_window.id() => {
-                        viewport.platform_request_close = true;
+                            if window_id == main_window.id() {
+                                (viewport.work_pos, viewport.work_size) = work_area(
+                                    [new_pos.x, new_pos.y],
+                                    viewport.size,
+                                    self.main_work_area_insets,
+                                );
+                            }
+
+                            viewport.platform_request_move = true;
+                        }
+                    }
+                    winit::event::WindowEvent::CloseRequested => {
+                        self.close_requests.push(viewport.id);
+
+                        if window_id != main_window.id() && !self.defer_close_requests {
+                            viewport.platform_request_close = true;
+                        }
+                    }
+                    winit::event::WindowEvent::HoveredFile(ref path) => {
+                        self.hovered_files.push((viewport.id, path.clone()));
                     }
-                    winit::event::WindowEvent::ReceivedCharacter(c) => {
+                    winit::event::WindowEvent::HoveredFileCancelled => {
+                        self.hovered_files.clear();
+                    }
+                    winit::event::WindowEvent::DroppedFile(ref path) => {
+                        self.hovered_files.clear();
+                        self.dropped_files.push((viewport.id, path.clone()));
+                    }
+                    // Backspace, enter, tab and friends also arrive as characters on some
+                    // platforms. They are handled as keys, as text they'd show up as
+                    // garbage glyphs.
+                    winit::event::WindowEvent::ReceivedCharacter(c) if !c.is_control() => {
                         imgui.io_mut().add_input_character(c);
                     }
-                    winit::event::WindowEvent::Focused(f) => unsafe {
-                        (*(viewport.platform_user_data.cast::<ViewportData>())).focus = f;
-                    },
+                    winit::event::WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                        viewport.dpi_scale = scale_factor as f32;
+
+                        if window_id == main_window.id() {
+                            self.main_scale_factor = scale_factor;
+                        }
+                    }
+                    winit::event::WindowEvent::ThemeChanged(theme) if self.theme != Some(theme) => {
+                        self.theme = Some(theme);
+
+                        if self.follow_theme {
+                            match theme {
+                                Theme::Dark => imgui.style_mut().use_dark_colors(),
+                                Theme::Light => imgui.style_mut().use_light_colors(),
+                            };
+                        }
+
+                        if let Some(on_theme_changed) = &mut self.on_theme_changed {
+                            on_theme_changed(theme);
+                        }
+                    }
+                    winit::event::WindowEvent::Occluded(occluded) => {
+                        if occluded {
+                            self.occluded_windows.insert(window_id);
+                        } else {
+                            self.occluded_windows.remove(&window_id);
+                        }
+                    }
+                    winit::event::WindowEvent::Focused(f) => {
+                        unsafe {
+                            (*(viewport.platform_user_data.cast::<ViewportData>())).focus = f;
+                        }
+
+                        if f {
+                            self.focused_window = Some(window_id);
+                        } else if self.focused_window == Some(window_id) {
+                            self.focused_window = None;
+                        }
+                    }
                     winit::event::WindowEvent::KeyboardInput {
                         input:
                             KeyboardInput {
-                                virtual_keycode: Some(key),
+                                virtual_keycode,
+                                scancode,
                                 state,
                                 ..
                             },
@@ -240,10 +776,15 @@ impl Renderer {
                         // applications to use either general "ctrl" or a
                         // specific key. Same applies to other modifiers.
                         // https://github.com/ocornut/imgui/issues/5047
-                        handle_key_modifier(imgui.io_mut(), key, pressed);
+                        if let Some(key) = virtual_keycode {
+                            handle_key_modifier(imgui.io_mut(), key, pressed);
+                        }
+                        if virtual_keycode == Some(VirtualKeyCode::LAlt) {
+                            self.left_alt_down = pressed;
+                        }
 
                         // Add main key event
-                        if let Some(key) = to_imgui_key(key) {
+                        if let Some(key) = self.imgui_key(scancode, virtual_keycode) {
                             imgui.io_mut().add_key_event(key, pressed);
                         }
                     }
@@ -252,59 +793,126 @@ impl Renderer {
                             .io_mut()
                             .add_key_event(Key::ModShift, modifiers.shift());
                         imgui.io_mut().add_key_event(Key::ModCtrl, modifiers.ctrl());
-                        imgui.io_mut().add_key_event(Key::ModAlt, modifiers.alt());
+                        if !modifiers.alt() {
+                            self.left_alt_down = false;
+                        }
+                        let alt = modifiers.alt() && (!RIGHT_ALT_IS_ALTGR || self.left_alt_down);
+                        imgui.io_mut().add_key_event(Key::ModAlt, alt);
                         imgui
                             .io_mut()
                             .add_key_event(Key::ModSuper, modifiers.logo());
+
+                        // A release that happened while another application had focus never
+                        // reaches us, so drop the sided keys of every modifier that is up now.
+                        // imgui ignores key events that don't change the state.
+                        for (held, keys) in [
+                            (modifiers.shift(), [Key::LeftShift, Key::RightShift]),
+                            (modifiers.ctrl(), [Key::LeftCtrl, Key::RightCtrl]),
+                            (modifiers.alt(), [Key::LeftAlt, Key::RightAlt]),
+                            (modifiers.logo(), [Key::LeftSuper, Key::RightSuper]),
+                        ] {
+                            if !held {
+                                for key in keys {
+                                    imgui.io_mut().add_key_event(key, false);
+                                }
+                            }
+                        }
                     }
                     winit::event::WindowEvent::CursorMoved { position, .. } => {
-                        if imgui
-                            .io()
-                            .config_flags
-                            .contains(ConfigFlags::VIEWPORTS_ENABLE)
-                        {
+                        let pos = if viewports_enabled {
                             let window_pos =
                                 window.inner_position().unwrap_or_default().cast::<f32>();
-                            let pos = [
+                            [
                                 position.x as f32 + window_pos.x,
                                 position.y as f32 + window_pos.y,
-                            ];
-                            imgui.io_mut().add_mouse_pos_event(pos);
+                            ]
                         } else {
-                            imgui
-                                .io_mut()
-                                .add_mouse_pos_event([position.x as f32, position.y as f32]);
+                            [position.x as f32, position.y as f32]
+                        };
+
+                        self.hovered_window = Some(window_id);
+                        if self.coalesce_cursor_moves {
+                            self.pending_mouse_pos = Some(pos);
+                        } else {
+                            imgui.io_mut().add_mouse_pos_event(pos);
                         }
                     }
-                    winit::event::WindowEvent::MouseWheel {
-                        delta,
-                        phase: TouchPhase::Moved,
-                        ..
-                    } => match delta {
-                        winit::event::MouseScrollDelta::LineDelta(h, v) => {
-                            imgui.io_mut().add_mouse_wheel_event([h, v]);
+                    winit::event::WindowEvent::CursorEntered { .. } => {
+                        self.hovered_window = Some(window_id);
+                    }
+                    // Leaving one of our windows for another reports the new window's
+                    // entry right after, so only the window the cursor is in may clear
+                    // the position. Otherwise imgui keeps hovering what was under it.
+                    winit::event::WindowEvent::CursorLeft { .. }
+                        if self.hovered_window == Some(window_id) =>
+                    {
+                        self.hovered_window = None;
+                        self.pending_mouse_pos = None;
+                        imgui.io_mut().add_mouse_pos_event(NO_MOUSE_POS);
+                    }
+                    // Every phase carries its own share of the scroll, touchpads also
+                    // report some with `Started` and `Ended`. Empty deltas are dropped.
+                    winit::event::WindowEvent::MouseWheel { delta, .. } => {
+                        let wheel = match delta {
+                            winit::event::MouseScrollDelta::LineDelta(h, v) => [h, v],
+                            winit::event::MouseScrollDelta::PixelDelta(pos) => [
+                                pos.x as f32 / self.pixels_per_line,
+                                pos.y as f32 / self.pixels_per_line,
+                            ],
+                        };
+
+                        if wheel != [0.0, 0.0] {
+                            // Scrolls whatever is under the cursor at the time, like clicks.
+                            if let Some(pos) = self.pending_mouse_pos.take() {
+                                imgui.io_mut().add_mouse_pos_event(pos);
+                            }
+                            imgui.io_mut().add_mouse_wheel_event(wheel);
                         }
-                        winit::event::MouseScrollDelta::PixelDelta(pos) => {
-                            let h = if pos.x > 0.0 {
-                                1.0
-                            } else if pos.x < 0.0 {
-                                -1.0
-                            } else {
-                                0.0
-                            };
-                            let v = if pos.y > 0.0 {
-                                1.0
-                            } else if pos.y < 0.0 {
-                                -1.0
-                            } else {
-                                0.0
-                            };
-                            imgui.io_mut().add_mouse_wheel_event([h, v]);
+                    }
+                    // Touches drive the mouse. imgui only learned to tell touch screens
+                    // apart through `AddMouseSourceEvent` in 1.89.5, newer than the imgui
+                    // linked by imgui-rs 0.11, so they are indistinguishable from a mouse.
+                    winit::event::WindowEvent::Touch(touch)
+                        if self.active_touch.is_none() || self.active_touch == Some(touch.id) =>
+                    {
+                        let pos = if viewports_enabled {
+                            let window_pos =
+                                window.inner_position().unwrap_or_default().cast::<f32>();
+                            [
+                                touch.location.x as f32 + window_pos.x,
+                                touch.location.y as f32 + window_pos.y,
+                            ]
+                        } else {
+                            [touch.location.x as f32, touch.location.y as f32]
+                        };
+
+                        self.pending_mouse_pos = None;
+                        imgui.io_mut().add_mouse_pos_event(pos);
+
+                        match touch.phase {
+                            TouchPhase::Started => {
+                                self.active_touch = Some(touch.id);
+                                imgui
+                                    .io_mut()
+                                    .add_mouse_button_event(MouseButton::Left, true);
+                            }
+                            TouchPhase::Moved => {}
+                            TouchPhase::Ended | TouchPhase::Cancelled => {
+                                self.active_touch = None;
+                                imgui
+                                    .io_mut()
+                                    .add_mouse_button_event(MouseButton::Left, false);
+                            }
                         }
-                    },
+                    }
                     winit::event::WindowEvent::MouseInput { state, button, .. } => {
                         let state = state == ElementState::Pressed;
 
+                        // Clicks have to land where the cursor was when they happened.
+                        if let Some(pos) = self.pending_mouse_pos.take() {
+                            imgui.io_mut().add_mouse_pos_event(pos);
+                        }
+
                         if let Some(button) = to_imgui_mouse_button(button) {
                             imgui.io_mut().add_mouse_button_event(button, state);
                         }
@@ -315,20 +923,40 @@ impl Renderer {
             winit::event::Event::DeviceEvent {
                 event:
                     DeviceEvent::Key(KeyboardInput {
-                        virtual_keycode: Some(key),
+                        virtual_keycode,
+                        scancode,
                         state: ElementState::Released,
                         ..
                     }),
                 ..
             } => {
-                if let Some(key) = to_imgui_key(key) {
+                // Must release the same key the press was reported as.
+                if let Some(key) = self.imgui_key(scancode, virtual_keycode) {
                     imgui.io_mut().add_key_event(key, false);
                 }
             }
+            winit::event::Event::MainEventsCleared => self.flush_pending_input(imgui),
             _ => {}
         }
     }
 
+    // Hands imgui the input that is held back until a batch of events is complete.
+    fn flush_pending_input(&mut self, imgui: &mut imgui::Context) {
+        if let Some(pos) = self.pending_mouse_pos.take() {
+            imgui.io_mut().add_mouse_pos_event(pos);
+        }
+
+        // Moving focus between two of our windows unfocuses one before focusing
+        // the other, so imgui is only told once all events of the batch are in.
+        let app_focused = self.focused_window.is_some();
+        if app_focused != self.app_focused {
+            self.app_focused = app_focused;
+            unsafe {
+                imgui::sys::ImGuiIO_AddFocusEvent(imgui.io_mut().raw_mut(), app_focused);
+            }
+        }
+    }
+
     pub fn update_viewports<T>(
         &mut self,
         imgui: &mut imgui::Context,
@@ -336,6 +964,55 @@ impl Renderer {
         device: &wgpu::Device,
         instance: &wgpu::Instance,
     ) -> Result<(), RendererError> {
+        if !self.supports_viewports {
+            if !self.warned_unsupported_viewports
+                && imgui
+                    .io()
+                    .config_flags
+                    .contains(ConfigFlags::VIEWPORTS_ENABLE)
+            {
+                diagnostic!(
+                    warn,
+                    "Viewports are enabled but not supported on this platform, ignoring them"
+                );
+                self.warned_unsupported_viewports = true;
+            }
+
+            self.event_queue.borrow_mut().clear();
+            return Ok(());
+        }
+
+        // `update_platform_windows` consumed the requests raised since the last frame.
+        // Not every imgui version clears them, and a stale flag would keep pulling the
+        // imgui window back to the platform window.
+        let ids = std::iter::once(self.main_viewport_id).chain(self.extra_windows.keys().copied());
+        for id in ids.collect::<Vec<_>>() {
+            if let Some(viewport) = imgui.viewport_by_id_mut(id) {
+                viewport.platform_request_move = false;
+                viewport.platform_request_resize = false;
+            }
+        }
+
+        self.process_viewport_events(imgui, window_target, device, instance)
+    }
+
+    /// Applies the queued viewport window changes without a frame, e.g. on
+    /// `MainEventsCleared` while nothing is drawn. Besides what imgui queued in its last
+    /// `update_platform_windows`, this covers changes the application requested since,
+    /// like `set_viewport_window_state` or `move_viewport_to_monitor`.
+    /// `update_viewports` calls it as well.
+    pub fn process_viewport_events<T>(
+        &mut self,
+        imgui: &mut imgui::Context,
+        window_target: &EventLoopWindowTarget<T>,
+        device: &wgpu::Device,
+        instance: &wgpu::Instance,
+    ) -> Result<(), RendererError> {
+        if !self.supports_viewports {
+            self.event_queue.borrow_mut().clear();
+            return Ok(());
+        }
+
         loop {
             let event = self.event_queue.borrow_mut().pop_front();
             let event = if let Some(event) = event {
@@ -347,37 +1024,127 @@ impl Renderer {
             match event {
                 ViewportEvent::Create(id) => {
                     if let Some(viewport) = imgui.viewport_by_id_mut(id) {
-                        let extra_window =
-                            self.create_extra_window(viewport, window_target, device, instance)?;
+                        let title = self
+                            .queued_title(id)
+                            .or_else(|| self.viewport_titles.get(&id).cloned())
+                            .unwrap_or_default();
+                        let extra_window = self.create_extra_window(
+                            viewport,
+                            &title,
+                            window_target,
+                            device,
+                            instance,
+                        )?;
+
+                        if let Some(on_viewport_created) = &mut self.on_viewport_created {
+                            on_viewport_created(id, &extra_window.window);
+                        }
                         self.extra_windows.insert(id, extra_window);
                     }
                 }
                 ViewportEvent::Destroy(id) => {
-                    self.extra_windows.remove(&id);
+                    self.requested_sizes.remove(&id);
+                    self.viewport_titles.remove(&id);
+                    if let Some(extra) = self.extra_windows.remove(&id) {
+                        self.occluded_windows.remove(&extra.window.id());
+                        drop(extra);
+
+                        if let Some(on_viewport_destroyed) = &mut self.on_viewport_destroyed {
+                            on_viewport_destroyed(id);
+                        }
+                    }
                 }
                 ViewportEvent::SetPos(id, pos) => {
-                    if let Some((_, _, wnd)) = self.extra_windows.get(&id) {
-                        wnd.set_outer_position(PhysicalPosition::new(pos[0], pos[1]));
+                    if let Some(extra) = self.extra_windows.get(&id) {
+                        extra.window.set_outer_position(PhysicalPosition::new(
+                            pos[0].round() as i32,
+                            pos[1].round() as i32,
+                        ));
+
+                        // Report where the window actually ended up, the OS may have
+                        // snapped or clamped it. Feeding the request back would drift.
+                        if let (Ok(actual), Some(viewport)) =
+                            (extra.window.inner_position(), imgui.viewport_by_id_mut(id))
+                        {
+                            let data = unsafe {
+                                viewport.platform_user_data.cast::<ViewportData>().as_mut()
+                            };
+                            if let Some(data) = data {
+                                data.pos = [actual.x as f32, actual.y as f32];
+                            }
+                        }
                     }
                 }
                 ViewportEvent::SetSize(id, size) => {
-                    if let Some((_, _, wnd)) = self.extra_windows.get(&id) {
-                        wnd.set_inner_size(PhysicalSize::new(size[0], size[1]));
+                    if let Some(extra) = self.extra_windows.get(&id) {
+                        // Never smaller than a pixel, which no surface can be configured
+                        // with, nor larger than the monitor the window is on.
+                        let max = extra
+                            .window
+                            .current_monitor()
+                            .map_or([u32::MAX; 2], |monitor| {
+                                [monitor.size().width.max(1), monitor.size().height.max(1)]
+                            });
+                        let size = [
+                            (size[0].round() as u32).clamp(1, max[0]),
+                            (size[1].round() as u32).clamp(1, max[1]),
+                        ];
+                        if extra.window.inner_size() != PhysicalSize::new(size[0], size[1]) {
+                            self.requested_sizes.insert(id, size);
+                            extra
+                                .window
+                                .set_inner_size(PhysicalSize::new(size[0], size[1]));
+                        }
                     }
                 }
+                // Shown once its first frame was presented, see `present_frames`.
                 ViewportEvent::SetVisible(id) => {
-                    if let Some((_, _, wnd)) = self.extra_windows.get(&id) {
-                        wnd.set_visible(true);
+                    if let Some(extra) = self.extra_windows.get_mut(&id) {
+                        extra.show_pending = true;
                     }
                 }
                 ViewportEvent::SetFocus(id) => {
-                    if let Some((_, _, wnd)) = self.extra_windows.get(&id) {
-                        wnd.focus_window();
+                    if let Some(extra) = self.extra_windows.get(&id) {
+                        extra.window.focus_window();
                     }
                 }
                 ViewportEvent::SetTitle(id, title) => {
-                    if let Some((_, _, wnd)) = self.extra_windows.get(&id) {
-                        wnd.set_title(&title);
+                    if let Some(extra) = self.extra_windows.get(&id) {
+                        extra.window.set_title(&title);
+                    }
+                    // imgui only sends titles that changed, windows made for a viewport
+                    // later on need the last one.
+                    self.viewport_titles.insert(id, title);
+                }
+                ViewportEvent::SetTopMost(id, top_most) => {
+                    if let Some(extra) = self.extra_windows.get(&id) {
+                        extra.window.set_always_on_top(top_most);
+                    }
+                }
+                // The surface follows through the `Resized` event winit sends afterwards.
+                ViewportEvent::SetSizeLimits(id, limits) => {
+                    if let Some(extra) = self.extra_windows.get(&id) {
+                        extra.window.set_min_inner_size(
+                            limits
+                                .min
+                                .map(|[width, height]| PhysicalSize::new(width, height)),
+                        );
+                        extra.window.set_max_inner_size(
+                            limits
+                                .max
+                                .map(|[width, height]| PhysicalSize::new(width, height)),
+                        );
+                    }
+                }
+                ViewportEvent::SetWindowState(id, state) => {
+                    if let Some(extra) = self.extra_windows.get(&id) {
+                        extra.window.set_fullscreen(
+                            (state == ViewportWindowState::Fullscreen)
+                                .then_some(Fullscreen::Borderless(None)),
+                        );
+                        extra
+                            .window
+                            .set_maximized(state == ViewportWindowState::Maximized);
                     }
                 }
             }
@@ -386,177 +1153,2274 @@ impl Renderer {
         Ok(())
     }
 
+    // imgui queues the title right behind the creation. Picking it up early means the
+    // window never shows up untitled.
+    fn queued_title(&self, id: Id) -> Option<String> {
+        self.event_queue
+            .borrow()
+            .iter()
+            .find_map(|event| match event {
+                ViewportEvent::SetTitle(title_id, title) if *title_id == id => Some(title.clone()),
+                _ => None,
+            })
+    }
+
     fn create_extra_window<T>(
         &mut self,
         viewport: &mut imgui::Viewport,
+        title: &str,
         window_target: &EventLoopWindowTarget<T>,
         device: &wgpu::Device,
         instance: &wgpu::Instance,
-    ) -> Result<(Option<SRenderer>, Surface, winit::window::Window), RendererError> {
-        let window_builder = WindowBuilder::new()
+    ) -> Result<ViewportWindow, RendererError> {
+        if let Some(placement) = self.restored_placements.remove(&viewport.id) {
+            viewport.pos = placement.pos;
+            viewport.size = placement.size;
+            if let Some(data) =
+                unsafe { viewport.platform_user_data.cast::<ViewportData>().as_mut() }
+            {
+                data.pos = placement.pos;
+                data.size = placement.size;
+            }
+            // Moves the imgui window along with its platform window.
+            viewport.platform_request_move = true;
+            viewport.platform_request_resize = true;
+        }
+
+        let state = self
+            .viewport_window_states
+            .get(&viewport.id)
+            .copied()
+            .unwrap_or_default();
+        let mut window_builder = WindowBuilder::new()
+            .with_title(title)
             .with_position(PhysicalPosition::new(viewport.pos[0], viewport.pos[1]))
             .with_inner_size(PhysicalSize::new(viewport.size[0], viewport.size[1]))
             .with_visible(false)
             .with_resizable(true)
-            .with_decorations(!viewport.flags.contains(ViewportFlags::NO_DECORATION));
+            .with_decorations(!viewport.flags.contains(ViewportFlags::NO_DECORATION))
+            .with_always_on_top(viewport.flags.contains(ViewportFlags::TOP_MOST))
+            .with_window_icon(self.viewport_icons.get(&viewport.id).cloned())
+            .with_maximized(state == ViewportWindowState::Maximized)
+            .with_fullscreen(
+                (state == ViewportWindowState::Fullscreen).then_some(Fullscreen::Borderless(None)),
+            )
+            .with_transparent(
+                self.transparent_all_viewports || self.transparent_viewports.contains(&viewport.id),
+            );
+
+        let limits = self
+            .viewport_size_limits
+            .get(&viewport.id)
+            .copied()
+            .unwrap_or_default();
+        if let Some([width, height]) = limits.min {
+            window_builder = window_builder.with_min_inner_size(PhysicalSize::new(width, height));
+        }
+        if let Some([width, height]) = limits.max {
+            window_builder = window_builder.with_max_inner_size(PhysicalSize::new(width, height));
+        }
+
+        // winit only supports resize increments on X11, and only at creation.
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ))]
+        if let Some(&[width, height]) = self.viewport_resize_increments.get(&viewport.id) {
+            use winit::platform::unix::WindowBuilderExtUnix;
+
+            window_builder =
+                window_builder.with_resize_increments(PhysicalSize::new(width, height));
+        }
+
+        if let Some(customize) = &self.window_builder {
+            window_builder = customize(viewport, window_builder);
+        }
 
         let window = window_builder.build(window_target).unwrap();
 
+        // `prepare_render` only touches the cursor when it changes, catch up on it.
+        if self.manage_cursor {
+            if let Some(cursor) = self.last_cursor {
+                window.set_cursor_icon(cursor);
+            }
+            if self.cursor_hidden {
+                window.set_cursor_visible(false);
+            }
+        }
+
         let surface = unsafe { instance.create_surface(&window).unwrap() };
 
-        let surface_desc = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            width: window.inner_size().width,
-            height: window.inner_size().height,
-            present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            view_formats: vec![wgpu::TextureFormat::Bgra8Unorm],
+        let mut extra = ViewportWindow {
+            renderer: None,
+            surface,
+            window,
+            targets: RenderTargets::default(),
+            show_pending: false,
+            present_mode: self
+                .present_mode_policy
+                .as_ref()
+                .map(|policy| policy(viewport)),
+            surface_size: [0, 0],
         };
 
-        surface.configure(device, &surface_desc);
+        // imgui may hand out an empty viewport, which no surface can be configured with.
+        let size = extra.window.inner_size();
+        let surface_desc =
+            self.viewport_surface_config(&extra, size.width.max(1), size.height.max(1));
+
+        extra.configure_surface(device, &surface_desc);
 
-        Ok((None, surface, window))
+        Ok(extra)
     }
-    fn to_winit_cursor(cursor: imgui::MouseCursor) -> winit::window::CursorIcon {
-        match cursor {
-            imgui::MouseCursor::Arrow => winit::window::CursorIcon::Default,
-            imgui::MouseCursor::TextInput => winit::window::CursorIcon::Text,
-            imgui::MouseCursor::ResizeAll => winit::window::CursorIcon::Move,
-            imgui::MouseCursor::ResizeNS => winit::window::CursorIcon::NsResize,
-            imgui::MouseCursor::ResizeEW => winit::window::CursorIcon::EwResize,
-            imgui::MouseCursor::ResizeNESW => winit::window::CursorIcon::NeswResize,
-            imgui::MouseCursor::ResizeNWSE => winit::window::CursorIcon::NwseResize,
-            imgui::MouseCursor::Hand => winit::window::CursorIcon::Hand,
-            imgui::MouseCursor::NotAllowed => winit::window::CursorIcon::NotAllowed,
+
+    /// Reconfigures the surface of a single viewport at its window's current size, e.g.
+    /// to recover after a driver glitch.
+    pub fn reconfigure_viewport(&mut self, id: Id, device: &wgpu::Device) {
+        let Some(size) = self
+            .extra_windows
+            .get(&id)
+            .map(|extra| extra.window.inner_size())
+        else {
+            return;
+        };
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        let Some(surface_desc) = self
+            .extra_windows
+            .get(&id)
+            .map(|extra| self.viewport_surface_config(extra, size.width, size.height))
+        else {
+            return;
+        };
+        if let Some(extra) = self.extra_windows.get_mut(&id) {
+            extra.configure_surface(device, &surface_desc);
+            extra.targets = RenderTargets::default();
         }
     }
 
-    pub fn render<'r>(
-        &'r mut self,
+    /// Caches the capabilities of the main surface, e.g.
+    /// `surface.get_capabilities(&adapter)`, best right after construction. Viewport
+    /// surfaces are assumed to support the same present modes and fall back to the
+    /// first of them when the requested one isn't among them. Fails if the texture
+    /// formats or the viewport alpha mode are unsupported, which would otherwise only
+    /// show once a viewport window gets created.
+    pub fn set_surface_capabilities(
+        &mut self,
+        capabilities: wgpu::SurfaceCapabilities,
+    ) -> Result<(), CreateRendererError> {
+        for format in [self.main_texture_format, self.viewport_format] {
+            if !capabilities.formats.contains(&format) {
+                return Err(CreateRendererError::UnsupportedFormat(format));
+            }
+        }
+
+        if self.viewport_alpha_mode != wgpu::CompositeAlphaMode::Auto
+            && !capabilities.alpha_modes.contains(&self.viewport_alpha_mode)
+        {
+            return Err(CreateRendererError::UnsupportedAlphaMode(
+                self.viewport_alpha_mode,
+            ));
+        }
+
+        self.surface_capabilities = Some(capabilities);
+
+        Ok(())
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.present_mode
+    }
+
+    /// Sets the present mode of the viewport surfaces, and of the main surface when the
+    /// renderer reconfigures it. `AutoVsync` by default, `AutoNoVsync` picks the fastest
+    /// supported mode without vsync. Existing windows are reconfigured right away.
+    pub fn set_present_mode(&mut self, device: &wgpu::Device, present_mode: wgpu::PresentMode) {
+        self.present_mode = present_mode;
+
+        let ids: Vec<Id> = self.extra_windows.keys().copied().collect();
+        for id in ids {
+            self.reconfigure_viewport(id, device);
+        }
+    }
+
+    /// Decides the present mode of every viewport window created from now on, e.g. to
+    /// let short-lived tooltips skip vsync while tool windows keep it. Without a policy
+    /// the windows use `present_mode`. Unsupported modes fall back the same way.
+    /// `transient_viewports_no_vsync` is a ready-made policy.
+    pub fn set_present_mode_policy(
+        &mut self,
+        policy: impl Fn(&imgui::Viewport) -> wgpu::PresentMode + 'static,
+    ) {
+        self.present_mode_policy = Some(Box::new(policy));
+    }
+
+    pub fn clear_present_mode_policy(&mut self) {
+        self.present_mode_policy = None;
+    }
+
+    fn make_surface_config(&self, width: u32, height: u32) -> wgpu::SurfaceConfiguration {
+        wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: self.viewport_format,
+            width,
+            height,
+            present_mode: supported_present_mode(
+                self.surface_capabilities.as_ref(),
+                self.present_mode,
+            ),
+            alpha_mode: self.viewport_alpha_mode,
+            view_formats: vec![self.viewport_view_format],
+        }
+    }
+
+    fn viewport_surface_config(
+        &self,
+        extra: &ViewportWindow,
+        width: u32,
+        height: u32,
+    ) -> wgpu::SurfaceConfiguration {
+        wgpu::SurfaceConfiguration {
+            present_mode: supported_present_mode(
+                self.surface_capabilities.as_ref(),
+                extra.present_mode.unwrap_or(self.present_mode),
+            ),
+            ..self.make_surface_config(width, height)
+        }
+    }
+
+    /// Rebuilds the window and surface of a viewport at its current position and size,
+    /// for attributes like transparency that winit can only apply at creation.
+    pub fn recreate_viewport_window<T>(
+        &mut self,
+        imgui: &mut imgui::Context,
+        id: Id,
+        window_target: &EventLoopWindowTarget<T>,
+        device: &wgpu::Device,
+        instance: &wgpu::Instance,
+    ) -> Result<(), RendererError> {
+        if !self.extra_windows.contains_key(&id) {
+            return Ok(());
+        }
+        let Some(viewport) = imgui.viewport_by_id_mut(id) else {
+            return Ok(());
+        };
+
+        // The old window stays registered until its replacement exists, so a failure
+        // leaves the viewport as it was.
+        let title = self.viewport_titles.get(&id).cloned().unwrap_or_default();
+        let mut extra =
+            self.create_extra_window(viewport, &title, window_target, device, instance)?;
+        let old = self.extra_windows.remove(&id).unwrap();
+
+        if let Ok(pos) = old.window.outer_position() {
+            extra.window.set_outer_position(pos);
+        }
+        extra.window.set_inner_size(old.window.inner_size());
+        extra.show_pending = old.show_pending;
+        extra
+            .window
+            .set_visible(!old.show_pending && old.window.is_visible().unwrap_or(true));
+
+        if self.focused_window == Some(old.window.id()) {
+            self.focused_window = Some(extra.window.id());
+        }
+        self.occluded_windows.remove(&old.window.id());
+
+        // The renderer only depends on the texture format, so it survives the new surface.
+        extra.renderer = old.renderer;
+
+        if let Some(on_viewport_created) = &mut self.on_viewport_created {
+            on_viewport_created(id, &extra.window);
+        }
+        self.extra_windows.insert(id, extra);
+
+        Ok(())
+    }
+
+    /// Requests per-pixel transparent windows for all viewports created from now on.
+    /// `alpha_modes` are the modes the surfaces support, e.g.
+    /// `surface.get_capabilities(&adapter).alpha_modes` of the main surface. Without a
+    /// premultiplied or postmultiplied mode among them the surfaces stay `Auto`.
+    pub fn set_transparent_viewports(
+        &mut self,
+        transparent: bool,
+        alpha_modes: &[wgpu::CompositeAlphaMode],
+    ) {
+        self.transparent_all_viewports = transparent;
+        self.viewport_alpha_mode = if transparent {
+            [
+                wgpu::CompositeAlphaMode::PreMultiplied,
+                wgpu::CompositeAlphaMode::PostMultiplied,
+            ]
+            .into_iter()
+            .find(|mode| alpha_modes.contains(mode))
+            .unwrap_or(wgpu::CompositeAlphaMode::Auto)
+        } else {
+            wgpu::CompositeAlphaMode::Auto
+        };
+    }
+
+    pub fn linear_output(&self) -> bool {
+        self.viewport_format == TextureFormat::Bgra8Unorm
+    }
+
+    /// Switches the surfaces of extra windows between `Bgra8UnormSrgb` (the default)
+    /// and `Bgra8Unorm`. With linear output the GPU no longer encodes to sRGB on write
+    /// and the viewport renderers use imgui-wgpu's sRGB shader instead, so imgui's
+    /// colors are stored as-is; this matches a main surface configured with a non-sRGB
+    /// format and avoids washed out viewports in that case. A config set with
+    /// `set_viewport_renderer_config` has to pick the shader itself. Existing windows
+    /// are reconfigured and get a new renderer on the next frame.
+    pub fn set_linear_output(&mut self, device: &wgpu::Device, linear: bool) {
+        (self.viewport_format, self.viewport_view_format) = if linear {
+            (TextureFormat::Bgra8Unorm, TextureFormat::Bgra8UnormSrgb)
+        } else {
+            (TextureFormat::Bgra8UnormSrgb, TextureFormat::Bgra8Unorm)
+        };
+        self.spare_renderer = None;
+
+        let ids: Vec<Id> = self.extra_windows.keys().copied().collect();
+        for id in ids {
+            if let Some(window) = self.extra_windows.get_mut(&id) {
+                window.renderer = None;
+            }
+            self.reconfigure_viewport(id, device);
+        }
+    }
+
+    /// Sets the color opaque extra windows are cleared to, passed to the GPU as-is. On
+    /// the default sRGB surfaces this is a linear color.
+    pub fn set_viewport_clear_color(&mut self, color: wgpu::Color) {
+        self.viewport_clear_color = color;
+        self.viewport_clear_color_srgb = false;
+    }
+
+    /// Like `set_viewport_clear_color`, but takes the color as picked in an sRGB color
+    /// picker. It is converted to linear whenever the viewport surfaces are sRGB so
+    /// the window shows exactly that color.
+    pub fn set_viewport_clear_color_srgb(&mut self, color: wgpu::Color) {
+        self.viewport_clear_color = color;
+        self.viewport_clear_color_srgb = true;
+    }
+
+    fn effective_viewport_clear_color(&self) -> wgpu::Color {
+        if self.viewport_clear_color_srgb && self.viewport_format.describe().srgb {
+            srgb_to_linear(self.viewport_clear_color)
+        } else {
+            self.viewport_clear_color
+        }
+    }
+
+    pub fn set_viewport_transparent<T>(
+        &mut self,
+        imgui: &mut imgui::Context,
+        id: Id,
+        transparent: bool,
+        window_target: &EventLoopWindowTarget<T>,
+        device: &wgpu::Device,
+        instance: &wgpu::Instance,
+    ) -> Result<(), RendererError> {
+        let changed = if transparent {
+            self.transparent_viewports.insert(id)
+        } else {
+            self.transparent_viewports.remove(&id)
+        };
+
+        if changed {
+            self.recreate_viewport_window(imgui, id, window_target, device, instance)?;
+        }
+
+        Ok(())
+    }
+
+    fn to_winit_cursor(cursor: imgui::MouseCursor) -> winit::window::CursorIcon {
+        match cursor {
+            imgui::MouseCursor::Arrow => winit::window::CursorIcon::Default,
+            imgui::MouseCursor::TextInput => winit::window::CursorIcon::Text,
+            imgui::MouseCursor::ResizeAll => winit::window::CursorIcon::Move,
+            imgui::MouseCursor::ResizeNS => winit::window::CursorIcon::NsResize,
+            imgui::MouseCursor::ResizeEW => winit::window::CursorIcon::EwResize,
+            imgui::MouseCursor::ResizeNESW => winit::window::CursorIcon::NeswResize,
+            imgui::MouseCursor::ResizeNWSE => winit::window::CursorIcon::NwseResize,
+            imgui::MouseCursor::Hand => winit::window::CursorIcon::Hand,
+            imgui::MouseCursor::NotAllowed => winit::window::CursorIcon::NotAllowed,
+        }
+    }
+
+    pub fn render<'r>(
+        &'r mut self,
         imgui: &mut imgui::Context,
         queue: &wgpu::Queue,
         device: &wgpu::Device,
         rpass: &mut wgpu::RenderPass<'r>,
     ) -> imgui_wgpu::RendererResult<()> {
-        self.main_renderer
-            .render(imgui.render(), queue, device, rpass)?;
+        let draw_data = imgui.render();
+
+        self.render_draw_data(draw_data, queue, device, rpass)
+    }
+
+    /// Like `render`, for draw data the application got from `imgui.render()` itself,
+    /// so generating it and recording it into a pass can happen at different places.
+    pub fn render_draw_data<'r>(
+        &'r mut self,
+        draw_data: &imgui::DrawData,
+        queue: &wgpu::Queue,
+        device: &wgpu::Device,
+        rpass: &mut wgpu::RenderPass<'r>,
+    ) -> imgui_wgpu::RendererResult<()> {
+        if !self.main_hidden() {
+            self.main_renderer.render(draw_data, queue, device, rpass)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn render_main(
+        &mut self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface: &Surface,
+        clear_color: wgpu::Color,
+    ) -> imgui_wgpu::RendererResult<()> {
+        imgui.render();
+
+        let mut frames = Vec::new();
+        let mut encoder: wgpu::CommandEncoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        match self.record_main(
+            imgui,
+            device,
+            queue,
+            surface,
+            clear_color,
+            &mut encoder,
+            &mut frames,
+        ) {
+            Ok(()) => {}
+            Err(ViewportRenderError::Renderer(_, e)) => return Err(e),
+            Err(ViewportRenderError::Surface(
+                _,
+                e @ (wgpu::SurfaceError::Lost | wgpu::SurfaceError::OutOfMemory),
+            )) => {
+                diagnostic!(error, "Dropped frame: {e:?}");
+                return Ok(());
+            }
+            Err(ViewportRenderError::Surface(_, e)) => {
+                diagnostic!(warn, "Dropped frame: {e:?}");
+                return Ok(());
+            }
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        self.present_frames(&mut frames);
+
+        Ok(())
+    }
+
+    /// Registers a texture for use with `imgui::Image` in every viewport. The texture
+    /// may have any sampleable format, independent of the surface format, and needs
+    /// `TextureUsages::TEXTURE_BINDING`.
+    pub fn register_texture(
+        &mut self,
+        device: &wgpu::Device,
+        texture: wgpu::Texture,
+        size: wgpu::Extent3d,
+    ) -> TextureId {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.add_texture(
+            device,
+            UserTexture {
+                texture: Arc::new(texture),
+                view: Arc::new(view),
+                size,
+                sampler: None,
+            },
+        )
+    }
+
+    /// Like `register_texture`, for a view the application keeps using itself, sampled
+    /// with `sampler`. The renderers hold on to `texture` and `view` until
+    /// `unregister_texture` is called with the returned id.
+    pub fn register_texture_view(
+        &mut self,
+        device: &wgpu::Device,
+        texture: Arc<wgpu::Texture>,
+        view: Arc<wgpu::TextureView>,
+        sampler: wgpu::SamplerDescriptor<'static>,
+    ) -> TextureId {
+        let size = texture.size();
+
+        self.add_texture(
+            device,
+            UserTexture {
+                texture,
+                view,
+                size,
+                sampler: Some(sampler),
+            },
+        )
+    }
+
+    fn add_texture(&mut self, device: &wgpu::Device, texture: UserTexture) -> TextureId {
+        let id = self.main_renderer.textures.insert(create_user_texture(
+            device,
+            &self.main_renderer,
+            &texture,
+        ));
+
+        // Every viewport renderer has its own texture map, keep the ids in sync.
+        for extra in self.extra_windows.values_mut() {
+            if let Some(renderer) = &mut extra.renderer {
+                register_user_texture(device, renderer, id, &texture);
+            }
+        }
+
+        self.textures.insert(id, texture);
+
+        id
+    }
+
+    /// Rebuilds the font atlas after fonts were added or changed and shares the new
+    /// texture with the main and every viewport renderer.
+    pub fn reload_font_texture(
+        &mut self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        self.font_texture = upload_font_atlas(imgui, device, queue);
+
+        let font_id = imgui.fonts().tex_id;
+        register_user_texture(device, &mut self.main_renderer, font_id, &self.font_texture);
+        for extra in self.extra_windows.values_mut() {
+            if let Some(renderer) = &mut extra.renderer {
+                register_user_texture(device, renderer, font_id, &self.font_texture);
+            }
+        }
+    }
+
+    pub fn unregister_texture(&mut self, id: TextureId) {
+        if self.textures.remove(&id).is_none() {
+            return;
+        }
+
+        self.main_renderer.textures.remove(id);
+        if let Some(renderer) = &mut self.spare_renderer {
+            renderer.textures.remove(id);
+        }
+        for extra in self.extra_windows.values_mut() {
+            if let Some(renderer) = &mut extra.renderer {
+                renderer.textures.remove(id);
+            }
+        }
+    }
+
+    /// Renders the main viewport into `view` instead of a surface, e.g. for screenshots
+    /// or headless tests. `view` must have the `texture_format` the renderer was created
+    /// with.
+    pub fn render_to_texture(
+        &mut self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        extent: wgpu::Extent3d,
+    ) -> imgui_wgpu::RendererResult<()> {
+        let draw_data = imgui.render();
+
+        let mut encoder: wgpu::CommandEncoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        record_draw_data(
+            &mut self.main_renderer,
+            draw_data,
+            view,
+            self.main_texture_format,
+            extent,
+            &mut self.offscreen_targets,
+            self.main_sample_count,
+            None,
+            self.supersample,
+            &mut self.blitters,
+            wgpu::LoadOp::Clear(VIEWPORT_CLEAR_COLOR),
+            device,
+            queue,
+            &mut encoder,
+        )?;
+
+        queue.submit(Some(encoder.finish()));
+
+        Ok(())
+    }
+
+    /// Renders every extra window (and the main window if its surface was handed over
+    /// with `set_main_surface`). All windows are recorded into one command encoder and
+    /// submitted at once, the frames are presented afterwards.
+    pub fn render_viewports(
+        &mut self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<(), ViewportRenderError> {
+        // Taken out for the frame so the renderer's other fields stay borrowable.
+        let main_surface = self.main_surface.take();
+        let result = self.render_frame(
+            imgui,
+            device,
+            queue,
+            main_surface
+                .as_ref()
+                .map(|surface| (surface, VIEWPORT_CLEAR_COLOR)),
+        );
+        self.main_surface = main_surface;
+
+        result
+    }
+
+    /// Renders the main window and every extra window with a single command encoder
+    /// and a single queue submission. Replaces the `render_main` + `render_viewports`
+    /// pair.
+    pub fn render_all_batched(
+        &mut self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface: &Surface,
+        clear_color: wgpu::Color,
+    ) -> Result<(), ViewportRenderError> {
+        self.render_frame(imgui, device, queue, Some((surface, clear_color)))
+    }
+
+    fn render_frame(
+        &mut self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        main: Option<(&Surface, wgpu::Color)>,
+    ) -> Result<(), ViewportRenderError> {
+        let mut result = Ok(());
+        let mut frames = std::mem::take(&mut self.frames);
+        let mut encoder: wgpu::CommandEncoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        if let Some((surface, clear_color)) = main {
+            imgui.render();
+            result = self.record_main(
+                imgui,
+                device,
+                queue,
+                surface,
+                clear_color,
+                &mut encoder,
+                &mut frames,
+            );
+        }
+
+        if self.supports_viewports {
+            result = result.and(self.record_extra_windows(
+                imgui,
+                device,
+                queue,
+                &mut encoder,
+                &mut frames,
+            ));
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        self.present_frames(&mut frames);
+        self.frames = frames;
+
+        result
+    }
+
+    /// Records the main viewport into `encoder` and queues its frame for presenting.
+    /// Expects `imgui.render()` to have been called.
+    #[allow(clippy::too_many_arguments)]
+    fn record_main(
+        &mut self,
+        imgui: &imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface: &Surface,
+        clear_color: wgpu::Color,
+        encoder: &mut wgpu::CommandEncoder,
+        frames: &mut Vec<(Id, wgpu::SurfaceTexture)>,
+    ) -> Result<(), ViewportRenderError> {
+        if self.main_hidden() {
+            return Ok(());
+        }
+
+        let main_viewport = imgui.main_viewport();
+        let frame = surface
+            .get_current_texture()
+            .map_err(|e| ViewportRenderError::Surface(main_viewport.id, e))?;
+
+        let recorded = record_draw_data(
+            &mut self.main_renderer,
+            main_viewport.draw_data(),
+            &frame
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+            frame.texture.format(),
+            frame.texture.size(),
+            &mut self.main_targets,
+            self.main_sample_count,
+            None,
+            self.supersample,
+            &mut self.blitters,
+            load_op(main_viewport.flags, clear_color),
+            device,
+            queue,
+            encoder,
+        );
+
+        // Presented even if recording failed, like the extra windows.
+        frames.push((main_viewport.id, frame));
+
+        recorded.map_err(|e| ViewportRenderError::Renderer(main_viewport.id, e))
+    }
+
+    fn present_frames(&mut self, frames: &mut Vec<(Id, wgpu::SurfaceTexture)>) {
+        for (id, frame) in frames.drain(..) {
+            frame.present();
+
+            // Shown only now that it has content, instead of flashing the empty window.
+            if let Some(extra) = self.extra_windows.get_mut(&id) {
+                if extra.show_pending {
+                    extra.window.set_visible(true);
+                    extra.show_pending = false;
+                }
+            }
+
+            if let Some(on_present) = &mut self.on_present {
+                on_present(id);
+            }
+        }
+    }
+
+    fn imgui_key(&self, scancode: u32, virtual_keycode: Option<VirtualKeyCode>) -> Option<Key> {
+        if self.physical_keys {
+            scancode_to_imgui_key(scancode).or_else(|| virtual_keycode.and_then(to_imgui_key))
+        } else {
+            virtual_keycode.and_then(to_imgui_key)
+        }
+    }
+
+    fn main_hidden(&self) -> bool {
+        self.main_minimized || self.occluded_windows.contains(&self.main_window_id)
+    }
+
+    /// Creates the renderers of new viewport windows now rather than on the first frame
+    /// they are rendered, and keeps one more ready for the next window, so dragging a
+    /// window out doesn't stall on pipeline creation. Call after `update_viewports`.
+    pub fn warm_up(
+        &mut self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        if !self.supports_viewports {
+            return;
+        }
+
+        self.ensure_viewport_renderers(imgui, device, queue);
+
+        if self.spare_renderer.is_none() {
+            self.spare_renderer = Some(self.create_viewport_renderer(imgui, device, queue));
+        }
+    }
+
+    fn ensure_viewport_renderers(
+        &mut self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let missing: Vec<Id> = self
+            .extra_windows
+            .iter()
+            .filter(|(_, extra)| extra.renderer.is_none())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in missing {
+            let renderer = match self.spare_renderer.take() {
+                Some(mut renderer) => {
+                    // Textures may have been registered since it was created.
+                    self.register_shared_textures(imgui, device, &mut renderer);
+                    renderer
+                }
+                None => self.create_viewport_renderer(imgui, device, queue),
+            };
+
+            if let Some(extra) = self.extra_windows.get_mut(&id) {
+                extra.renderer = Some(renderer);
+            }
+        }
+    }
+
+    fn create_viewport_renderer(
+        &self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> SRenderer {
+        let mut renderer = SRenderer::new(
+            imgui,
+            device,
+            queue,
+            RendererConfig {
+                texture_format: self.viewport_format,
+                sample_count: self.sample_count,
+                depth_format: self.viewport_depth_format,
+                ..self.viewport_renderer_config.as_ref().map_or_else(
+                    || default_renderer_config(self.viewport_format),
+                    |config| config(),
+                )
+            },
+        );
+
+        self.register_shared_textures(imgui, device, &mut renderer);
+
+        renderer
+    }
+
+    fn register_shared_textures(
+        &self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        renderer: &mut SRenderer,
+    ) {
+        // `SRenderer::new` always uploads its own copy of the font atlas, which can't be
+        // avoided. Swapping in the shared one frees that copy again, so only the memory
+        // is saved, not the upload.
+        register_user_texture(device, renderer, imgui.fonts().tex_id, &self.font_texture);
+
+        for (texture_id, texture) in &self.textures {
+            register_user_texture(device, renderer, *texture_id, texture);
+        }
+    }
+
+    fn record_extra_windows(
+        &mut self,
+        imgui: &mut imgui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        frames: &mut Vec<(Id, wgpu::SurfaceTexture)>,
+    ) -> Result<(), ViewportRenderError> {
+        let sample_count = self.sample_count;
+        let depth_format = self.viewport_depth_format;
+        let supersample = self.supersample;
+        let viewport_clear_color = self.effective_viewport_clear_color();
+        // The window map is borrowed mutably below, so the config is built up front.
+        let surface_template = self.make_surface_config(0, 0);
+        let mut result = Ok(());
+
+        // A viewport destroyed since the last `update_viewports` has no draw data left.
+        // Free its window right away instead of waiting for the queued destroy.
+        let on_viewport_destroyed = &mut self.on_viewport_destroyed;
+        let occluded_windows = &mut self.occluded_windows;
+        self.extra_windows.retain(|id, extra| {
+            let alive = imgui.viewport_by_id(*id).is_some();
+            if !alive {
+                occluded_windows.remove(&extra.window.id());
+                if let Some(on_viewport_destroyed) = on_viewport_destroyed {
+                    on_viewport_destroyed(*id);
+                }
+            }
+            alive
+        });
+
+        self.ensure_viewport_renderers(imgui, device, queue);
+
+        for (
+            id,
+            ViewportWindow {
+                renderer,
+                surface,
+                window,
+                targets,
+                show_pending,
+                present_mode,
+                surface_size,
+            },
+        ) in &mut self.extra_windows
+        {
+            if let Some(viewport) = imgui.viewport_by_id(*id) {
+                let Some(draw_data) = viewport_draw_data(viewport) else {
+                    diagnostic!(
+                        debug,
+                        "Viewport {id:?} has no draw data yet, was imgui rendered?"
+                    );
+                    continue;
+                };
+
+                // Nothing of a covered window is visible, skip acquiring and presenting.
+                // A window still waiting for its first frame is shown by presenting, so
+                // it has to present even while the OS reports it as covered.
+                if !*show_pending && self.occluded_windows.contains(&window.id()) {
+                    continue;
+                }
+
+                // Follow a resize that wasn't reported yet. This has to happen before the
+                // frame is acquired, wgpu can't reconfigure a surface with a frame out.
+                let window_size = window.inner_size();
+                if window_size.width != 0
+                    && window_size.height != 0
+                    && [window_size.width, window_size.height] != *surface_size
+                {
+                    let surface_desc = wgpu::SurfaceConfiguration {
+                        width: window_size.width,
+                        height: window_size.height,
+                        present_mode: supported_present_mode(
+                            self.surface_capabilities.as_ref(),
+                            present_mode.unwrap_or(self.present_mode),
+                        ),
+                        ..surface_template.clone()
+                    };
+
+                    surface.configure(device, &surface_desc);
+                    *surface_size = [window_size.width, window_size.height];
+                }
+
+                let frame = match surface.get_current_texture() {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        // A window that can't present must not stay hidden forever.
+                        if *show_pending {
+                            window.set_visible(true);
+                            *show_pending = false;
+                        }
+                        result = result.and(Err(ViewportRenderError::Surface(*id, e)));
+                        continue;
+                    }
+                };
+
+                let clear_color =
+                    if self.transparent_all_viewports || self.transparent_viewports.contains(id) {
+                        wgpu::Color::TRANSPARENT
+                    } else {
+                        viewport_clear_color
+                    };
+
+                if let Some(renderer) = renderer {
+                    if let Err(e) = record_draw_data(
+                        renderer,
+                        draw_data,
+                        &frame
+                            .texture
+                            .create_view(&wgpu::TextureViewDescriptor::default()),
+                        frame.texture.format(),
+                        frame.texture.size(),
+                        targets,
+                        sample_count,
+                        depth_format,
+                        supersample,
+                        &mut self.blitters,
+                        load_op(viewport.flags, clear_color),
+                        device,
+                        queue,
+                        encoder,
+                    ) {
+                        result = result.and(Err(ViewportRenderError::Renderer(*id, e)));
+                    }
+                }
+
+                frames.push((*id, frame));
+            }
+        }
+
+        result
+    }
+
+    /// Hands the main window's surface over to the renderer so `render_viewports`
+    /// also renders and presents the main viewport. `render` must not be called
+    /// in this mode.
+    pub fn set_main_surface(&mut self, surface: Surface) {
+        self.main_surface = Some(surface);
+        self.main_targets = RenderTargets::default();
+    }
+
+    pub fn take_main_surface(&mut self) -> Option<Surface> {
+        self.main_targets = RenderTargets::default();
+        self.main_surface.take()
+    }
+
+    /// The placement of every extra window, to be saved next to imgui's ini data.
+    /// Viewport ids are derived from window names and stay the same between runs.
+    pub fn viewport_placements(&self) -> Vec<(Id, ViewportPlacement)> {
+        self.extra_windows
+            .iter()
+            .map(|(id, extra)| {
+                let pos = extra
+                    .window
+                    .outer_position()
+                    .unwrap_or_default()
+                    .cast::<f32>();
+                let size = extra.window.inner_size().cast::<f32>();
+                (
+                    *id,
+                    ViewportPlacement {
+                        pos: [pos.x, pos.y],
+                        size: [size.width, size.height],
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Places the windows of the given viewports where `viewport_placements` saw them,
+    /// once imgui creates them. Call at startup, before the first `update_viewports`.
+    pub fn restore_viewports(
+        &mut self,
+        placements: impl IntoIterator<Item = (Id, ViewportPlacement)>,
+    ) {
+        self.restored_placements.extend(placements);
+    }
+
+    /// Maximizes or fullscreens the window of viewport `id`, now or once imgui creates
+    /// it. Applied by the next `update_viewports`.
+    pub fn set_viewport_window_state(&mut self, id: Id, state: ViewportWindowState) {
+        self.viewport_window_states.insert(id, state);
+        self.event_queue
+            .borrow_mut()
+            .push_back(ViewportEvent::SetWindowState(id, state));
+    }
+
+    /// Keeps the window of a viewport from being resized beyond `limits`, e.g. so a torn
+    /// off inspector can't shrink below a usable size. Kept for windows imgui recreates.
+    pub fn set_viewport_size_limits(&mut self, id: Id, limits: ViewportSizeLimits) {
+        if limits == ViewportSizeLimits::default() {
+            self.viewport_size_limits.remove(&id);
+        } else {
+            self.viewport_size_limits.insert(id, limits);
+        }
+        self.event_queue
+            .borrow_mut()
+            .push_back(ViewportEvent::SetSizeLimits(id, limits));
+    }
+
+    /// Lets the window of a viewport only be resized in steps of `increments` pixels,
+    /// e.g. for a tile previewer. winit supports this only on X11 and only when the
+    /// window is created, so it applies to the viewport's next window; call
+    /// `recreate_viewport_window` to apply it to the current one. The snapped size
+    /// arrives with the usual resize, which the surface and imgui follow.
+    pub fn set_viewport_resize_increments(&mut self, id: Id, increments: Option<[u32; 2]>) {
+        match increments {
+            Some(increments) => {
+                self.viewport_resize_increments.insert(id, increments);
+            }
+            None => {
+                self.viewport_resize_increments.remove(&id);
+            }
+        }
+    }
+
+    pub fn viewport_size_limits(&self, id: Id) -> ViewportSizeLimits {
+        self.viewport_size_limits
+            .get(&id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set_viewport_icon(&mut self, id: Id, icon: Option<Icon>) {
+        if let Some(extra) = self.extra_windows.get(&id) {
+            extra.window.set_window_icon(icon.clone());
+        }
+
+        // Remembered so the icon survives imgui destroying and recreating the viewport.
+        match icon {
+            Some(icon) => {
+                self.viewport_icons.insert(id, icon);
+            }
+            None => {
+                self.viewport_icons.remove(&id);
+            }
+        }
+    }
+
+    /// Refresh rate in millihertz of the monitor the center of the viewport is on.
+    pub fn monitor_refresh_rate(&self, imgui: &imgui::Context, id: Id) -> Option<u32> {
+        let viewport = imgui.viewport_by_id(id)?;
+        let center = [
+            viewport.pos[0] + viewport.size[0] / 2.0,
+            viewport.pos[1] + viewport.size[1] / 2.0,
+        ];
+
+        self.monitors[self.monitor_at(center)?].refresh_rate_millihertz
+    }
+
+    /// Index of the monitor the center of the viewport is on, in the order winit listed
+    /// the monitors at construction.
+    pub fn viewport_monitor(&self, imgui: &imgui::Context, id: Id) -> Option<usize> {
+        let viewport = imgui.viewport_by_id(id)?;
+
+        self.monitor_at([
+            viewport.pos[0] + viewport.size[0] / 2.0,
+            viewport.pos[1] + viewport.size[1] / 2.0,
+        ])
+    }
+
+    /// Centers the window of viewport `id` on the monitor at `monitor_index`. Returns
+    /// false if there is no such viewport window or monitor.
+    pub fn move_viewport_to_monitor(
+        &mut self,
+        imgui: &mut imgui::Context,
+        id: Id,
+        monitor_index: usize,
+    ) -> bool {
+        if !self.extra_windows.contains_key(&id) {
+            return false;
+        }
+        let (Some(monitor), Some(viewport)) = (
+            self.monitors.get(monitor_index),
+            imgui.viewport_by_id_mut(id),
+        ) else {
+            return false;
+        };
+
+        let pos = [
+            monitor.pos[0] + ((monitor.size[0] - viewport.size[0]) / 2.0).max(0.0),
+            monitor.pos[1] + ((monitor.size[1] - viewport.size[1]) / 2.0).max(0.0),
+        ];
+        let data = unsafe { viewport.platform_user_data.cast::<ViewportData>().as_mut() };
+        if let Some(data) = data {
+            data.pos = pos;
+        }
+        self.event_queue
+            .borrow_mut()
+            .push_back(ViewportEvent::SetPos(id, pos));
+
+        true
+    }
+
+    /// Lists the monitors again, e.g. after one was plugged in or out, and moves viewport
+    /// windows left on a monitor that is gone onto the primary monitor.
+    pub fn refresh_monitors(&mut self, imgui: &mut imgui::Context, window: &winit::window::Window) {
+        let (monitors, monitor_infos) = collect_monitors(window);
+        imgui
+            .platform_io_mut()
+            .monitors
+            .replace_from_slice(&monitors);
+        self.monitors = monitor_infos;
+
+        let primary = window
+            .primary_monitor()
+            .and_then(|primary| {
+                let pos = [primary.position().x as f32, primary.position().y as f32];
+                self.monitors.iter().position(|monitor| monitor.pos == pos)
+            })
+            .unwrap_or(0);
+
+        let ids: Vec<Id> = self.extra_windows.keys().copied().collect();
+        for id in ids {
+            let Some(viewport) = imgui.viewport_by_id(id) else {
+                continue;
+            };
+            let center = [
+                viewport.pos[0] + viewport.size[0] / 2.0,
+                viewport.pos[1] + viewport.size[1] / 2.0,
+            ];
+
+            if self.monitor_at(center).is_none() {
+                diagnostic!(
+                    debug,
+                    "Viewport {id:?} is off every monitor, moving it to the primary one"
+                );
+                self.move_viewport_to_monitor(imgui, id, primary);
+            }
+        }
+    }
+
+    fn monitor_at(&self, point: [f32; 2]) -> Option<usize> {
+        self.monitors.iter().position(|monitor| {
+            point[0] >= monitor.pos[0]
+                && point[1] >= monitor.pos[1]
+                && point[0] < monitor.pos[0] + monitor.size[0]
+                && point[1] < monitor.pos[1] + monitor.size[1]
+        })
+    }
+
+    pub fn pixels_per_line(&self) -> f32 {
+        self.pixels_per_line
+    }
+
+    /// How many pixels of a `PixelDelta` scroll (trackpads) make up one imgui wheel step.
+    pub fn set_pixels_per_line(&mut self, pixels_per_line: f32) {
+        self.pixels_per_line = pixels_per_line.max(f32::EPSILON);
+    }
+
+    pub fn coalesce_cursor_moves(&self) -> bool {
+        self.coalesce_cursor_moves
+    }
+
+    /// Forward only the last cursor position of each event batch to imgui, flushed on
+    /// `MainEventsCleared`. Clicks and scrolls still see the position they happened at.
+    /// Useful with high polling rate mice.
+    pub fn set_coalesce_cursor_moves(&mut self, coalesce: bool) {
+        self.coalesce_cursor_moves = coalesce;
+    }
+
+    /// Drains the viewports whose window received a close request since the last call,
+    /// the main viewport included.
+    pub fn take_close_requests(&mut self) -> Vec<Id> {
+        std::mem::take(&mut self.close_requests)
+    }
+
+    /// Drains the files dropped onto any of our windows since the last call, with the
+    /// viewport of the window they landed on. imgui's own drag and drop is not involved.
+    pub fn take_dropped_files(&mut self) -> Vec<(Id, PathBuf)> {
+        std::mem::take(&mut self.dropped_files)
+    }
+
+    /// The files currently dragged over one of our windows, e.g. to highlight a drop
+    /// target. Empty again once they are dropped or the drag leaves.
+    pub fn hovered_files(&self) -> &[(Id, PathBuf)] {
+        &self.hovered_files
+    }
+
+    /// When enabled, closing an extra window no longer closes its viewport right away.
+    /// The application collects the requests with `take_close_requests` and honors them
+    /// with `close_viewport`.
+    pub fn set_defer_close_requests(&mut self, defer: bool) {
+        self.defer_close_requests = defer;
+    }
+
+    pub fn close_viewport(&mut self, imgui: &mut imgui::Context, id: Id) {
+        if let Some(viewport) = imgui.viewport_by_id_mut(id) {
+            viewport.platform_request_close = true;
+        }
+    }
+
+    /// Hands the window of an extra viewport over to the application. Its surface and
+    /// renderer are dropped and imgui is asked to close the viewport, as if the user had
+    /// clicked the window's close button.
+    ///
+    /// imgui only honors that for imgui windows with a close button. Any other window
+    /// keeps the viewport alive and gets a new platform window on the next frame. Call
+    /// it outside of a frame, `update_platform_windows` drops the close request.
+    pub fn take_viewport_window(
+        &mut self,
+        imgui: &mut imgui::Context,
+        id: Id,
+    ) -> Option<winit::window::Window> {
+        let ViewportWindow {
+            renderer,
+            surface,
+            window,
+            targets,
+            ..
+        } = self.release_platform_window(imgui, id)?;
+
+        // The surface must go before the window it was created from.
+        drop(surface);
+        drop(renderer);
+        drop(targets);
+
+        Some(window)
+    }
+
+    /// Closes the window of an extra viewport right away and asks imgui to close the
+    /// viewport. imgui closes the imgui windows in it that have a close button, any
+    /// other window gets a new platform window on the next frame. Call it outside of a
+    /// frame, `update_platform_windows` drops the close request. Returns false for the
+    /// main viewport and for ids without a window.
+    pub fn destroy_viewport(&mut self, imgui: &mut imgui::Context, id: Id) -> bool {
+        if id == self.main_viewport_id {
+            return false;
+        }
+        let Some(extra) = self.release_platform_window(imgui, id) else {
+            return false;
+        };
+        drop(extra);
+
+        if let Some(on_viewport_destroyed) = &mut self.on_viewport_destroyed {
+            on_viewport_destroyed(id);
+        }
+
+        true
+    }
+
+    // Takes the window away from a viewport that lives on in imgui. imgui's own
+    // `DestroyPlatformWindow` is mirrored, so if the close request is ignored imgui
+    // sees a viewport without a platform window and creates a new one instead of
+    // drawing into a window that is gone.
+    fn release_platform_window(
+        &mut self,
+        imgui: &mut imgui::Context,
+        id: Id,
+    ) -> Option<ViewportWindow> {
+        let extra = self.extra_windows.remove(&id)?;
+
+        let window_id = extra.window.id();
+        if self.focused_window == Some(window_id) {
+            self.focused_window = None;
+        }
+        if self.hovered_window == Some(window_id) {
+            self.hovered_window = None;
+        }
+        self.occluded_windows.remove(&window_id);
+        self.requested_sizes.remove(&id);
+
+        if let Some(viewport) = imgui.viewport_by_id_mut(id) {
+            viewport.platform_request_close = true;
+            free_viewport_data(viewport);
+
+            let raw = viewport as *mut imgui::Viewport as *mut imgui::sys::ImGuiViewport;
+            unsafe { (*raw).PlatformWindowCreated = false };
+        }
+
+        Some(extra)
+    }
+
+    /// Reserves `[left, top, right, bottom]` pixels of the main window for application
+    /// chrome such as a native menu bar, so the main viewport's work area (and with it
+    /// `dockspace_over_main_viewport`) leaves them free.
+    pub fn set_main_work_area_insets(&mut self, imgui: &mut imgui::Context, insets: [f32; 4]) {
+        self.main_work_area_insets = insets;
+
+        let viewport = imgui.main_viewport_mut();
+        (viewport.work_pos, viewport.work_size) = work_area(viewport.pos, viewport.size, insets);
+    }
+
+    /// Called with the viewport id right after each window's frame got presented.
+    pub fn set_on_present(&mut self, on_present: impl FnMut(Id) + 'static) {
+        self.on_present = Some(Box::new(on_present));
+    }
+
+    pub fn clear_on_present(&mut self) {
+        self.on_present = None;
+    }
+
+    /// Called whenever a viewport got its window, before it is first shown, e.g. to
+    /// attach per-window state. `recreate_viewport_window` calls it again with the new
+    /// window.
+    pub fn set_on_viewport_created(
+        &mut self,
+        on_viewport_created: impl FnMut(Id, &winit::window::Window) + 'static,
+    ) {
+        self.on_viewport_created = Some(Box::new(on_viewport_created));
+    }
+
+    pub fn clear_on_viewport_created(&mut self) {
+        self.on_viewport_created = None;
+    }
+
+    /// Called with the viewport id when the window of a viewport is destroyed.
+    pub fn set_on_viewport_destroyed(&mut self, on_viewport_destroyed: impl FnMut(Id) + 'static) {
+        self.on_viewport_destroyed = Some(Box::new(on_viewport_destroyed));
+    }
+
+    pub fn clear_on_viewport_destroyed(&mut self) {
+        self.on_viewport_destroyed = None;
+    }
+
+    /// The system theme as last reported by any window, `None` until winit reported one.
+    pub fn theme(&self) -> Option<Theme> {
+        self.theme
+    }
+
+    /// Switches imgui between its dark and light colors whenever the system theme
+    /// changes. Off by default, as it overwrites any custom style colors.
+    pub fn set_follow_theme(&mut self, follow_theme: bool) {
+        self.follow_theme = follow_theme;
+    }
+
+    /// Called with the new theme whenever the system theme changes, e.g. to switch to
+    /// an application specific style.
+    pub fn set_on_theme_changed(&mut self, on_theme_changed: impl FnMut(Theme) + 'static) {
+        self.on_theme_changed = Some(Box::new(on_theme_changed));
+    }
+
+    pub fn clear_on_theme_changed(&mut self) {
+        self.on_theme_changed = None;
+    }
+
+    /// Lets the application adjust the builder of every extra window after the crate
+    /// applied its defaults, e.g. for size limits or an X11 class name.
+    pub fn set_window_builder(
+        &mut self,
+        window_builder: impl Fn(&imgui::Viewport, WindowBuilder) -> WindowBuilder + 'static,
+    ) {
+        self.window_builder = Some(Box::new(window_builder));
+    }
+
+    pub fn clear_window_builder(&mut self) {
+        self.window_builder = None;
+    }
+
+    /// The scale factor of the main window, as last reported by winit.
+    pub fn scale_factor(&self) -> f64 {
+        self.main_scale_factor
+    }
+
+    /// The scale factor of the window showing the viewport `id`, the main one included.
+    pub fn viewport_scale_factor(&self, id: Id) -> Option<f64> {
+        if id == self.main_viewport_id {
+            Some(self.main_scale_factor)
+        } else {
+            self.extra_windows
+                .get(&id)
+                .map(|extra| extra.window.scale_factor())
+        }
+    }
+
+    /// Number of extra viewport windows currently open.
+    pub fn viewport_count(&self) -> usize {
+        self.extra_windows.len()
+    }
+
+    /// Number of viewport windows that currently hold a renderer. Windows get theirs
+    /// on the first frame they are rendered, or earlier through `warm_up`.
+    pub fn active_renderers(&self) -> usize {
+        self.extra_windows
+            .values()
+            .filter(|extra| extra.renderer.is_some())
+            .count()
+    }
+
+    /// Counts what imgui produced for the main viewport and every extra window since the
+    /// last `imgui.render()`, e.g. to spot a runaway tool window. Occluded windows are
+    /// counted even though their frames are skipped.
+    pub fn render_stats(&self, imgui: &imgui::Context) -> RenderStats {
+        let mut stats = RenderStats::default();
+
+        let ids = std::iter::once(self.main_viewport_id).chain(self.extra_windows.keys().copied());
+        for id in ids {
+            let Some(draw_data) = imgui.viewport_by_id(id).and_then(viewport_draw_data) else {
+                continue;
+            };
+            let viewport = draw_stats(draw_data);
+
+            stats.total.vertices += viewport.vertices;
+            stats.total.indices += viewport.indices;
+            stats.total.draw_calls += viewport.draw_calls;
+            stats.viewports.push((id, viewport));
+        }
+
+        stats
+    }
+
+    /// The winit windows backing the extra viewports, for per-window tweaks the crate
+    /// doesn't wrap. The main window is not included.
+    pub fn viewport_windows(&self) -> impl Iterator<Item = (Id, &winit::window::Window)> {
+        self.extra_windows
+            .iter()
+            .map(|(id, extra)| (*id, &extra.window))
+    }
+
+    /// The platform and renderer backend names reported to imgui, for bug reports.
+    pub fn backend_info(&self) -> (String, String) {
+        (self.platform_name.clone(), self.renderer_name.clone())
+    }
+
+    /// Whether the platform can create viewport windows. Always false on Wayland and
+    /// the web, and after a failed `probe_viewport_support`.
+    pub fn supports_viewports(&self) -> bool {
+        self.supports_viewports
+    }
+
+    /// Checks that the adapter can present to a secondary window by creating a hidden
+    /// one, meant to be called once right after construction. If it can't, viewports
+    /// are turned off and `RENDERER_HAS_VIEWPORTS` is cleared so imgui keeps every
+    /// window inside the main one. Returns whether viewports remain supported.
+    pub fn probe_viewport_support<T>(
+        &mut self,
+        imgui: &mut imgui::Context,
+        window_target: &EventLoopWindowTarget<T>,
+        instance: &wgpu::Instance,
+        adapter: &wgpu::Adapter,
+    ) -> bool {
+        if !self.supports_viewports {
+            return false;
+        }
+
+        let supported = WindowBuilder::new()
+            .with_visible(false)
+            .with_inner_size(PhysicalSize::new(1, 1))
+            .build(window_target)
+            .ok()
+            .is_some_and(|window| {
+                unsafe { instance.create_surface(&window) }
+                    .is_ok_and(|surface| adapter.is_surface_supported(&surface))
+            });
+
+        if !supported {
+            self.supports_viewports = false;
+            self.close_extra_windows();
+            imgui
+                .io_mut()
+                .backend_flags
+                .remove(imgui::BackendFlags::RENDERER_HAS_VIEWPORTS);
+        }
+
+        supported
+    }
+
+    /// Like the free `enable_docking_and_viewports`, but also closes every extra window
+    /// right away when viewports get turned off.
+    pub fn enable_docking_and_viewports(
+        &mut self,
+        io: &mut imgui::Io,
+        docking: bool,
+        viewports: bool,
+    ) {
+        enable_docking_and_viewports(io, docking, viewports);
+
+        if !viewports {
+            self.close_extra_windows();
+        }
+    }
+
+    fn close_extra_windows(&mut self) {
+        for (id, extra) in self.extra_windows.drain() {
+            self.occluded_windows.remove(&extra.window.id());
+            drop(extra);
+
+            if let Some(on_viewport_destroyed) = &mut self.on_viewport_destroyed {
+                on_viewport_destroyed(id);
+            }
+        }
+    }
+
+    pub fn physical_keys(&self) -> bool {
+        self.physical_keys
+    }
+
+    /// When enabled, letter, digit and punctuation keys are reported by their position
+    /// on a US QWERTY keyboard instead of the symbol the layout puts there, so e.g.
+    /// WASD bindings work on AZERTY too. Text input is unaffected.
+    pub fn set_physical_keys(&mut self, physical_keys: bool) {
+        self.physical_keys = physical_keys;
+    }
+
+    /// Shows `cursor` on all windows instead of the cursor imgui asks for, unless imgui
+    /// hides the cursor. winit 0.27 has no bitmap cursors, so this is limited to the
+    /// system cursors.
+    pub fn set_cursor_override(&mut self, cursor: Option<CursorIcon>) {
+        self.cursor_override = cursor;
+    }
+
+    pub fn manage_cursor(&self) -> bool {
+        self.manage_cursor
+    }
+
+    /// When disabled `prepare_render` leaves the cursor icon of every window alone so
+    /// the application can drive it itself.
+    pub fn set_manage_cursor(&mut self, manage_cursor: bool) {
+        self.manage_cursor = manage_cursor;
+        // Forces the icon to be reapplied once management is turned back on.
+        self.last_cursor = None;
+    }
+
+    pub fn supersample(&self) -> f32 {
+        self.supersample
+    }
+
+    /// Renders every viewport at `supersample` times its window size and downscales
+    /// on present. Only applies to `render_main` and `render_viewports`, a render
+    /// pass passed to `render` must already be sized accordingly.
+    pub fn set_supersample(&mut self, io: &mut imgui::Io, supersample: f32) {
+        self.supersample = supersample.max(1.0);
+        io.display_framebuffer_scale = [self.supersample, self.supersample];
+
+        self.main_targets = RenderTargets::default();
+        for extra in self.extra_windows.values_mut() {
+            extra.targets = RenderTargets::default();
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        let sample_count = sample_count.max(1);
+        if self.sample_count == sample_count {
+            return;
+        }
+
+        self.sample_count = sample_count;
+
+        // Viewport renderers bake the sample count into their pipeline,
+        // so they are recreated lazily on the next `render_viewports`.
+        self.spare_renderer = None;
+        for extra in self.extra_windows.values_mut() {
+            extra.renderer = None;
+            extra.targets.msaa = None;
+        }
+    }
+
+    /// Supplies the config viewport renderers are created from, e.g. to use the same
+    /// custom shader as the main renderer. Its texture format, sample count and depth
+    /// format are replaced by the viewport settings. `RendererConfig` can't be cloned,
+    /// hence the constructor function. Existing viewports get a new renderer on the
+    /// next frame.
+    pub fn set_viewport_renderer_config(
+        &mut self,
+        config: impl Fn() -> RendererConfig<'static> + 'static,
+    ) {
+        self.viewport_renderer_config = Some(Box::new(config));
+
+        self.spare_renderer = None;
+        for extra in self.extra_windows.values_mut() {
+            extra.renderer = None;
+        }
+    }
+
+    pub fn viewport_depth_format(&self) -> Option<TextureFormat> {
+        self.viewport_depth_format
+    }
+
+    /// Gives every extra window a depth(-stencil) buffer of `depth_format`, sized with
+    /// the window and cleared each frame, for compositing imgui with 3D content. `None`
+    /// (the default) renders without one.
+    pub fn set_viewport_depth_format(&mut self, depth_format: Option<TextureFormat>) {
+        if self.viewport_depth_format == depth_format {
+            return;
+        }
+
+        self.viewport_depth_format = depth_format;
+
+        // Like the sample count the depth format is part of the pipeline.
+        self.spare_renderer = None;
+        for extra in self.extra_windows.values_mut() {
+            extra.renderer = None;
+            extra.targets.depth = None;
+        }
+    }
+
+    /// Call before `imgui.frame()`. Advances imgui's clock by `delta`, feeds a coalesced
+    /// cursor move or focus change that is still pending and warps the cursor if imgui
+    /// asked for it.
+    pub fn prepare_frame(
+        &mut self,
+        imgui: &mut imgui::Context,
+        window: &winit::window::Window,
+        delta: Duration,
+    ) {
+        imgui.io_mut().update_delta_time(delta);
+        self.flush_pending_input(imgui);
+
+        if imgui.io().want_set_mouse_pos {
+            self.set_mouse_pos(imgui, window);
+        }
+    }
+
+    /// Applies the mouse cursor imgui asked for to every window. Cursor warps happen in
+    /// `prepare_frame`.
+    pub fn prepare_render(&mut self, imgui: &mut imgui::Context, window: &winit::window::Window) {
+        if !self.manage_cursor {
+            return;
+        }
+
+        let Some(cursor) = imgui.mouse_cursor() else {
+            // A hidden cursor wins over the override.
+            if !self.cursor_hidden {
+                window.set_cursor_visible(false);
+                for extra in self.extra_windows.values() {
+                    extra.window.set_cursor_visible(false);
+                }
+                self.cursor_hidden = true;
+            }
+            return;
+        };
+
+        if self.cursor_hidden {
+            window.set_cursor_visible(true);
+            for extra in self.extra_windows.values() {
+                extra.window.set_cursor_visible(true);
+            }
+            self.cursor_hidden = false;
+        }
+
+        let cursor = self
+            .cursor_override
+            .unwrap_or_else(|| Self::to_winit_cursor(cursor));
+
+        if self.last_cursor != Some(cursor) {
+            window.set_cursor_icon(cursor);
+
+            for extra in self.extra_windows.values() {
+                extra.window.set_cursor_icon(cursor);
+            }
+
+            self.last_cursor = Some(cursor);
+        }
+    }
+
+    /// Runs the per-frame viewport steps in the order they depend on each other: ends the
+    /// frame, applies the cursor, lets imgui update its platform windows, creates and
+    /// updates the viewport windows and warms up their renderers. Call once the `Ui` of
+    /// the frame is no longer used.
+    ///
+    /// Rendering the main window (`render_main` or `render`) and presenting it is left to
+    /// the caller, followed by `render_viewports`.
+    pub fn update<T>(
+        &mut self,
+        imgui: &mut imgui::Context,
+        window: &winit::window::Window,
+        window_target: &EventLoopWindowTarget<T>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instance: &wgpu::Instance,
+    ) -> Result<(), RendererError> {
+        // Same as `Ui::end_frame_early`, imgui ignores it if the frame already ended.
+        unsafe { imgui::sys::igEndFrame() };
+
+        self.prepare_render(imgui, window);
+
+        imgui.update_platform_windows();
+        self.update_viewports(imgui, window_target, device, instance)?;
+        self.warm_up(imgui, device, queue);
+
+        Ok(())
+    }
+
+    fn set_mouse_pos(&self, imgui: &imgui::Context, main_window: &winit::window::Window) {
+        let [x, y] = imgui.io().mouse_pos;
+
+        if !imgui
+            .io()
+            .config_flags
+            .contains(ConfigFlags::VIEWPORTS_ENABLE)
+        {
+            let _ = main_window.set_cursor_position(PhysicalPosition::new(x, y));
+            return;
+        }
+
+        // With viewports the position is in screen space, warp inside whichever window
+        // contains it.
+        let contains = |window: &winit::window::Window| {
+            let pos = window.inner_position().unwrap_or_default().cast::<f32>();
+            let size = window.inner_size().cast::<f32>();
+            x >= pos.x && y >= pos.y && x < pos.x + size.width && y < pos.y + size.height
+        };
+
+        let window = self
+            .extra_windows
+            .values()
+            .map(|extra| &extra.window)
+            .find(|window| contains(window))
+            .unwrap_or(main_window);
+
+        let pos = window.inner_position().unwrap_or_default().cast::<f32>();
+        let _ = window.set_cursor_position(PhysicalPosition::new(x - pos.x, y - pos.y));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_draw_data(
+    renderer: &mut SRenderer,
+    draw_data: &imgui::DrawData,
+    view: &wgpu::TextureView,
+    format: TextureFormat,
+    size: wgpu::Extent3d,
+    targets: &mut RenderTargets,
+    sample_count: u32,
+    depth_format: Option<TextureFormat>,
+    supersample: f32,
+    blitters: &mut HashMap<TextureFormat, Blitter>,
+    load: wgpu::LoadOp<wgpu::Color>,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    encoder: &mut wgpu::CommandEncoder,
+) -> imgui_wgpu::RendererResult<()> {
+    let target_size = supersampled_size([size.width, size.height], supersample);
+
+    if targets.format != Some(format) {
+        *targets = RenderTargets {
+            format: Some(format),
+            ..Default::default()
+        };
+    }
+
+    if supersample > 1.0 {
+        if !matches!(&targets.supersample, Some((_, s)) if *s == target_size) {
+            targets.blit_bind_group = None;
+            targets.supersample = Some((
+                create_target_view(
+                    device,
+                    format,
+                    target_size,
+                    1,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                ),
+                target_size,
+            ));
+        }
+    } else {
+        targets.supersample = None;
+        targets.blit_bind_group = None;
+    }
 
-        Ok(())
+    if sample_count > 1 {
+        if !matches!(&targets.msaa, Some((_, s)) if *s == target_size) {
+            targets.msaa = Some((
+                create_target_view(
+                    device,
+                    format,
+                    target_size,
+                    sample_count,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT,
+                ),
+                target_size,
+            ));
+        }
+    } else {
+        targets.msaa = None;
     }
 
-    pub fn render_viewports(
-        &mut self,
-        imgui: &mut imgui::Context,
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        texture_format: TextureFormat,
-    ) {
-        for (id, (renderer, surface, window)) in &mut self.extra_windows {
-            if renderer.is_none() {
-                *renderer = Some(SRenderer::new(
-                    imgui,
+    if let Some(depth_format) = depth_format {
+        if !matches!(&targets.depth, Some((_, s)) if *s == target_size) {
+            targets.depth = Some((
+                create_target_view(
                     device,
-                    queue,
-                    RendererConfig {
-                        texture_format,
-                        ..Default::default()
-                    },
-                ));
-            }
+                    depth_format,
+                    target_size,
+                    sample_count,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT,
+                ),
+                target_size,
+            ));
+        }
+    } else {
+        targets.depth = None;
+    }
 
-            if let Some(viewport) = imgui.viewport_by_id(*id) {
-                let draw_data = viewport.draw_data();
+    let output = targets.supersample.as_ref().map_or(view, |(view, _)| view);
+    let (attachment, resolve_target) = match targets.msaa.as_ref() {
+        Some((msaa, _)) => (msaa, Some(output)),
+        None => (output, None),
+    };
 
-                let mut encoder: wgpu::CommandEncoder =
-                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: attachment,
+            resolve_target,
+            ops: wgpu::Operations { load, store: true },
+        })],
+        depth_stencil_attachment: targets.depth.as_ref().zip(depth_format).map(
+            |((view, _), depth_format)| wgpu::RenderPassDepthStencilAttachment {
+                view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: has_stencil(depth_format).then_some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: true,
+                }),
+            },
+        ),
+    });
 
-                let frame = match surface.get_current_texture() {
-                    Ok(frame) => frame,
-                    Err(e) => {
-                        eprintln!("Dropped frame: {e:?}");
-                        continue;
-                    }
-                };
+    if draw_data.draw_lists_count() > 0 {
+        renderer.render(draw_data, queue, device, &mut rpass)?;
+    }
 
-                let size = frame.texture.size();
-                let window_size = window.inner_size();
-                if window_size.width != size.width && window_size.height != size.height {
-                    let surface_desc = wgpu::SurfaceConfiguration {
-                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                        format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                        width: window_size.width,
-                        height: window_size.height,
-                        present_mode: wgpu::PresentMode::Fifo,
-                        alpha_mode: wgpu::CompositeAlphaMode::Auto,
-                        view_formats: vec![wgpu::TextureFormat::Bgra8Unorm],
-                    };
+    drop(rpass);
 
-                    surface.configure(device, &surface_desc);
-                }
+    if let Some((source, _)) = &targets.supersample {
+        let blitter = blitters
+            .entry(format)
+            .or_insert_with(|| Blitter::new(device, format));
+        let bind_group = targets
+            .blit_bind_group
+            .get_or_insert_with(|| blitter.bind_group(device, source));
+        blitter.blit(encoder, bind_group, view);
+    }
 
-                let view = frame
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default());
-                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                    label: None,
-                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.0,
-                                g: 0.0,
-                                b: 0.0,
-                                a: 1.0,
-                            }),
-                            store: true,
-                        },
-                    })],
-                    depth_stencil_attachment: None,
-                });
+    Ok(())
+}
 
-                if let Some(renderer) = renderer {
-                    renderer
-                        .render(draw_data, queue, device, &mut rpass)
-                        .expect("Failed render");
-                }
+// A minimized window reports a zero size, imgui keeps laying out against the last one.
+// The names imgui shows in its metrics window, tagged with the crate version.
+fn backend_names() -> (String, String) {
+    (
+        format!(
+            "imgui-winit-wgpu-platform-viewports {}",
+            env!("CARGO_PKG_VERSION")
+        ),
+        format!(
+            "imgui-winit-wgpu-renderer-viewports {}",
+            env!("CARGO_PKG_VERSION")
+        ),
+    )
+}
 
-                drop(rpass);
+fn apply_display_size(io: &mut imgui::Io, size: PhysicalSize<u32>) {
+    if size.width != 0 && size.height != 0 {
+        io.display_size = [size.width as f32, size.height as f32];
+    }
+}
 
-                queue.submit(Some(encoder.finish()));
+fn work_area(
+    pos: [f32; 2],
+    size: [f32; 2],
+    [left, top, right, bottom]: [f32; 4],
+) -> ([f32; 2], [f32; 2]) {
+    (
+        [pos[0] + left, pos[1] + top],
+        [
+            (size[0] - left - right).max(0.0),
+            (size[1] - top - bottom).max(0.0),
+        ],
+    )
+}
 
-                frame.present();
-            }
+fn srgb_to_linear(color: wgpu::Color) -> wgpu::Color {
+    let convert = |c: f64| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
         }
+    };
+
+    wgpu::Color {
+        r: convert(color.r),
+        g: convert(color.g),
+        b: convert(color.b),
+        a: color.a,
     }
+}
 
-    pub fn prepare_render(&mut self, imgui: &mut imgui::Context, window: &winit::window::Window) {
-        if let Some(cursor) = imgui.mouse_cursor() {
-            let cursor = Self::to_winit_cursor(cursor);
+// The size imgui draws at before the result is downscaled onto a `size` window.
+fn supersampled_size(size: [u32; 2], supersample: f32) -> [u32; 2] {
+    if supersample > 1.0 {
+        [
+            (size[0] as f32 * supersample).round() as u32,
+            (size[1] as f32 * supersample).round() as u32,
+        ]
+    } else {
+        size
+    }
+}
 
-            if self.last_cursor != cursor {
-                window.set_cursor_icon(cursor);
+// imgui's colors are sRGB. The default shader linearizes them for sRGB targets, which
+// encode them again on write, while a linear target needs them written unchanged.
+fn default_renderer_config(format: TextureFormat) -> RendererConfig<'static> {
+    if format.describe().srgb {
+        RendererConfig::new()
+    } else {
+        RendererConfig::new_srgb()
+    }
+}
 
-                for (_, _, wnd) in self.extra_windows.values() {
-                    wnd.set_cursor_icon(cursor);
-                }
+fn load_op(flags: ViewportFlags, clear_color: wgpu::Color) -> wgpu::LoadOp<wgpu::Color> {
+    // The application draws its own background behind imgui in these viewports.
+    if flags.contains(ViewportFlags::NO_RENDERER_CLEAR) {
+        wgpu::LoadOp::Load
+    } else {
+        wgpu::LoadOp::Clear(clear_color)
+    }
+}
 
-                self.last_cursor = cursor;
-            }
+fn register_user_texture(
+    device: &wgpu::Device,
+    renderer: &mut SRenderer,
+    id: TextureId,
+    texture: &UserTexture,
+) {
+    let texture = create_user_texture(device, renderer, texture);
+    renderer.textures.replace(id, texture);
+}
+
+fn create_user_texture(
+    device: &wgpu::Device,
+    renderer: &SRenderer,
+    texture: &UserTexture,
+) -> Texture {
+    let config = texture
+        .sampler
+        .as_ref()
+        .map(|sampler_desc| RawTextureConfig {
+            label: Some("imgui-wgpu-winit user texture"),
+            sampler_desc: sampler_desc.clone(),
+        });
+
+    Texture::from_raw_parts(
+        device,
+        renderer,
+        texture.texture.clone(),
+        texture.view.clone(),
+        None,
+        config.as_ref(),
+        texture.size,
+    )
+}
+
+fn upload_font_atlas(
+    imgui: &mut imgui::Context,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> UserTexture {
+    let fonts = imgui.fonts();
+    let atlas = fonts.build_rgba32_texture();
+    let size = wgpu::Extent3d {
+        width: atlas.width,
+        height: atlas.height,
+        depth_or_array_layers: 1,
+    };
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("imgui-wgpu-winit font atlas"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        atlas.data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(atlas.width * 4),
+            rows_per_image: std::num::NonZeroU32::new(atlas.height),
+        },
+        size,
+    );
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    UserTexture {
+        texture: Arc::new(texture),
+        view: Arc::new(view),
+        size,
+        sampler: None,
+    }
+}
+
+fn collect_monitors(
+    window: &winit::window::Window,
+) -> (Vec<imgui::PlatformMonitor>, Vec<MonitorInfo>) {
+    let mut monitors = Vec::new();
+    let mut monitor_infos = Vec::new();
+    for monitor in window.available_monitors() {
+        monitors.push(imgui::PlatformMonitor {
+            main_pos: [monitor.position().x as f32, monitor.position().y as f32],
+            main_size: [monitor.size().width as f32, monitor.size().height as f32],
+            work_pos: [monitor.position().x as f32, monitor.position().y as f32],
+            work_size: [monitor.size().width as f32, monitor.size().height as f32],
+            dpi_scale: monitor.scale_factor() as f32,
+        });
+
+        let modes: Vec<_> = monitor
+            .video_modes()
+            .map(|mode| (mode.size(), mode.refresh_rate_millihertz()))
+            .collect();
+        let refresh_rate_millihertz = pick_refresh_rate(monitor.size(), &modes);
+
+        monitor_infos.push(MonitorInfo {
+            pos: [monitor.position().x as f32, monitor.position().y as f32],
+            size: [monitor.size().width as f32, monitor.size().height as f32],
+            refresh_rate_millihertz,
+        });
+    }
+
+    (monitors, monitor_infos)
+}
+
+// Prefers the fastest mode at the monitor's current resolution, falling back to the
+// fastest mode overall. `modes` holds each video mode's size and refresh rate in mHz.
+fn pick_refresh_rate(size: PhysicalSize<u32>, modes: &[(PhysicalSize<u32>, u32)]) -> Option<u32> {
+    modes
+        .iter()
+        .filter(|(mode_size, _)| *mode_size == size)
+        .map(|(_, rate)| *rate)
+        .max()
+        .or_else(|| modes.iter().map(|(_, rate)| *rate).max())
+}
+
+fn free_viewport_data(viewport: &mut imgui::Viewport) {
+    if !viewport.platform_user_data.is_null() {
+        unsafe {
+            drop(Box::from_raw(
+                viewport.platform_user_data.cast::<ViewportData>(),
+            ));
         }
+        viewport.platform_user_data = null_mut();
     }
 }
 
-pub fn enable_docking_and_viewports(io: &mut imgui::Io, docking: bool, viewports: bool) {
-    if docking {
-        io.config_flags.insert(ConfigFlags::DOCKING_ENABLE);
+fn viewport_draw_data(viewport: &imgui::Viewport) -> Option<&imgui::DrawData> {
+    // `Viewport::draw_data` dereferences the pointer unchecked, which is null until
+    // imgui rendered the viewport for the first time.
+    let raw = viewport as *const imgui::Viewport as *const imgui::sys::ImGuiViewport;
+    let draw_data = unsafe { (*raw).DrawData };
+
+    if draw_data.is_null() || !unsafe { (*draw_data).Valid } {
+        None
+    } else {
+        Some(viewport.draw_data())
+    }
+}
+
+fn draw_stats(draw_data: &imgui::DrawData) -> DrawStats {
+    let draw_calls = draw_data
+        .draw_lists()
+        .flat_map(|list| list.commands())
+        .filter(|cmd| matches!(cmd, imgui::DrawCmd::Elements { .. }))
+        .count();
+
+    DrawStats {
+        vertices: draw_data.total_vtx_count as u32,
+        indices: draw_data.total_idx_count as u32,
+        draw_calls: draw_calls as u32,
+    }
+}
+
+/// A present mode policy for `Renderer::set_present_mode_policy`: `AutoNoVsync` for
+/// viewports that take no input, like tooltips, `AutoVsync` for everything else. The
+/// automatic modes work on every surface, so no `set_surface_capabilities` is needed.
+pub fn transient_viewports_no_vsync(viewport: &imgui::Viewport) -> wgpu::PresentMode {
+    if viewport.flags.contains(ViewportFlags::NO_INPUTS) {
+        wgpu::PresentMode::AutoNoVsync
+    } else {
+        wgpu::PresentMode::AutoVsync
+    }
+}
+
+fn supported_present_mode(
+    capabilities: Option<&wgpu::SurfaceCapabilities>,
+    present_mode: wgpu::PresentMode,
+) -> wgpu::PresentMode {
+    // wgpu resolves the automatic modes itself, they are never listed as supported.
+    if matches!(
+        present_mode,
+        wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+    ) {
+        return present_mode;
+    }
+
+    match capabilities {
+        Some(capabilities) if !capabilities.present_modes.contains(&present_mode) => capabilities
+            .present_modes
+            .first()
+            .copied()
+            .unwrap_or(wgpu::PresentMode::Fifo),
+        _ => present_mode,
     }
-    if viewports {
-        io.config_flags.insert(ConfigFlags::VIEWPORTS_ENABLE);
+}
+
+fn has_stencil(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::Stencil8
+            | TextureFormat::Depth24PlusStencil8
+            | TextureFormat::Depth32FloatStencil8
+    )
+}
+
+fn create_target_view(
+    device: &wgpu::Device,
+    format: TextureFormat,
+    size: [u32; 2],
+    sample_count: u32,
+    usage: wgpu::TextureUsages,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("imgui-wgpu-winit viewport target"),
+            size: wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+pub fn enable_docking_and_viewports(io: &mut imgui::Io, docking: bool, viewports: bool) {
+    io.config_flags.set(ConfigFlags::DOCKING_ENABLE, docking);
+    io.config_flags
+        .set(ConfigFlags::VIEWPORTS_ENABLE, viewports);
+}
+
+// imgui-rs has no names for the DPI flags of the docking branch.
+const DPI_ENABLE_SCALE_VIEWPORTS: ConfigFlags = unsafe {
+    ConfigFlags::from_bits_unchecked(imgui::sys::ImGuiConfigFlags_DpiEnableScaleViewports)
+};
+const DPI_ENABLE_SCALE_FONTS: ConfigFlags =
+    unsafe { ConfigFlags::from_bits_unchecked(imgui::sys::ImGuiConfigFlags_DpiEnableScaleFonts) };
+
+pub fn enable_dpi_scaling(io: &mut imgui::Io, fonts: bool, viewports: bool) {
+    io.config_flags.set(DPI_ENABLE_SCALE_FONTS, fonts);
+    io.config_flags.set(DPI_ENABLE_SCALE_VIEWPORTS, viewports);
+}
+
+/// Makes the next window begun on `ui` open in an OS window of its own at `pos` with
+/// `size`, instead of inside the main window. imgui creates viewports only for its
+/// windows, so there is no way to open one on its own; this is how an application
+/// spawns a secondary window programmatically. The OS window is created by
+/// `update_viewports`. The user can still dock the window back. `detached_window`
+/// also begins the window and returns its viewport id.
+pub fn detach_next_window(_ui: &imgui::Ui, pos: [f32; 2], size: [f32; 2]) {
+    unsafe {
+        imgui::sys::igSetNextWindowPos(
+            imgui::sys::ImVec2::new(pos[0], pos[1]),
+            imgui::sys::ImGuiCond_Appearing as imgui::sys::ImGuiCond,
+            imgui::sys::ImVec2::zero(),
+        );
+        imgui::sys::igSetNextWindowSize(
+            imgui::sys::ImVec2::new(size[0], size[1]),
+            imgui::sys::ImGuiCond_Appearing as imgui::sys::ImGuiCond,
+        );
+
+        let class = imgui::sys::ImGuiWindowClass_ImGuiWindowClass();
+        (*class).ViewportFlagsOverrideSet =
+            imgui::sys::ImGuiViewportFlags_NoAutoMerge as imgui::sys::ImGuiViewportFlags;
+        imgui::sys::igSetNextWindowClass(class);
+        imgui::sys::ImGuiWindowClass_destroy(class);
     }
 }
 
+/// Begins a window named `name` that opens in an OS window of its own, see
+/// `detach_next_window`, and runs `build` inside it. Returns the id of the window's
+/// viewport along with the result of `build`, or `None` while the window is collapsed.
+pub fn detached_window<R>(
+    ui: &imgui::Ui,
+    name: &str,
+    pos: [f32; 2],
+    size: [f32; 2],
+    build: impl FnOnce() -> R,
+) -> Option<(Id, R)> {
+    detach_next_window(ui, pos, size);
+
+    ui.window(name).build(|| {
+        let viewport = unsafe { &*imgui::sys::igGetWindowViewport().cast::<imgui::Viewport>() };
+        (viewport.id, build())
+    })
+}
+
 impl imgui::PlatformViewportBackend for PlatformBackend {
     fn create_window(&mut self, viewport: &mut imgui::Viewport) {
         viewport.platform_user_data = Box::into_raw(Box::new(ViewportData {
@@ -564,6 +3428,7 @@ impl imgui::PlatformViewportBackend for PlatformBackend {
             size: viewport.size,
             focus: false,
             minimized: false,
+            top_most: viewport.flags.contains(ViewportFlags::TOP_MOST),
         }))
         .cast();
         self.event_queue
@@ -572,12 +3437,7 @@ impl imgui::PlatformViewportBackend for PlatformBackend {
     }
 
     fn destroy_window(&mut self, viewport: &mut imgui::Viewport) {
-        unsafe {
-            drop(Box::from_raw(
-                viewport.platform_user_data.cast::<ViewportData>(),
-            ));
-        }
-        viewport.platform_user_data = null_mut();
+        free_viewport_data(viewport);
 
         self.event_queue
             .borrow_mut()
@@ -591,23 +3451,37 @@ impl imgui::PlatformViewportBackend for PlatformBackend {
     }
 
     fn set_window_pos(&mut self, viewport: &mut imgui::Viewport, pos: [f32; 2]) {
+        // imgui may read the position back before winit reports the move. Windows only
+        // land on whole pixels, so store the position the same way `SetPos` rounds it.
+        let pos = pos.map(f32::round);
+        let data = unsafe { viewport.platform_user_data.cast::<ViewportData>().as_mut() };
+        if let Some(data) = data {
+            data.pos = pos;
+        }
         self.event_queue
             .borrow_mut()
             .push_back(ViewportEvent::SetPos(viewport.id, pos));
     }
 
     fn get_window_pos(&mut self, viewport: &mut imgui::Viewport) -> [f32; 2] {
-        unsafe { (*(viewport.platform_user_data.cast::<ViewportData>())).pos }
+        unsafe { viewport.platform_user_data.cast::<ViewportData>().as_ref() }
+            .map_or(viewport.pos, |data| data.pos)
     }
 
     fn set_window_size(&mut self, viewport: &mut imgui::Viewport, size: [f32; 2]) {
+        let size = size.map(f32::round);
+        let data = unsafe { viewport.platform_user_data.cast::<ViewportData>().as_mut() };
+        if let Some(data) = data {
+            data.size = size;
+        }
         self.event_queue
             .borrow_mut()
             .push_back(ViewportEvent::SetSize(viewport.id, size));
     }
 
     fn get_window_size(&mut self, viewport: &mut imgui::Viewport) -> [f32; 2] {
-        unsafe { (*(viewport.platform_user_data.cast::<ViewportData>())).size }
+        unsafe { viewport.platform_user_data.cast::<ViewportData>().as_ref() }
+            .map_or(viewport.size, |data| data.size)
     }
 
     fn set_window_focus(&mut self, viewport: &mut imgui::Viewport) {
@@ -617,11 +3491,13 @@ impl imgui::PlatformViewportBackend for PlatformBackend {
     }
 
     fn get_window_focus(&mut self, viewport: &mut imgui::Viewport) -> bool {
-        unsafe { (*(viewport.platform_user_data.cast::<ViewportData>())).focus }
+        unsafe { viewport.platform_user_data.cast::<ViewportData>().as_ref() }
+            .is_some_and(|data| data.focus)
     }
 
     fn get_window_minimized(&mut self, viewport: &mut imgui::Viewport) -> bool {
-        unsafe { (*(viewport.platform_user_data.cast::<ViewportData>())).minimized }
+        unsafe { viewport.platform_user_data.cast::<ViewportData>().as_ref() }
+            .is_some_and(|data| data.minimized)
     }
 
     fn set_window_title(&mut self, viewport: &mut imgui::Viewport, title: &str) {
@@ -632,7 +3508,18 @@ impl imgui::PlatformViewportBackend for PlatformBackend {
 
     fn set_window_alpha(&mut self, _viewport: &mut imgui::Viewport, _alpha: f32) {}
 
-    fn update_window(&mut self, _viewport: &mut imgui::Viewport) {}
+    fn update_window(&mut self, viewport: &mut imgui::Viewport) {
+        let top_most = viewport.flags.contains(ViewportFlags::TOP_MOST);
+        let data = unsafe { viewport.platform_user_data.cast::<ViewportData>().as_mut() };
+        if let Some(data) = data {
+            if data.top_most != top_most {
+                data.top_most = top_most;
+                self.event_queue
+                    .borrow_mut()
+                    .push_back(ViewportEvent::SetTopMost(viewport.id, top_most));
+            }
+        }
+    }
 
     fn render_window(&mut self, _viewport: &mut imgui::Viewport) {}
 
@@ -662,18 +3549,31 @@ impl imgui::RendererViewportBackend for RendererBackend {
     fn swap_buffers(&mut self, _viewport: &mut imgui::Viewport) {}
 }
 
+// On X11 and Wayland AltGr arrives as a lone right alt. Reporting it as the alt
+// modifier makes imgui toggle its menu layer while typing e.g. `@` on a German
+// layout, so it only counts as `RightAlt` there. Windows sends AltGr as ctrl + alt,
+// which imgui already lets text through for.
+const RIGHT_ALT_IS_ALTGR: bool = !cfg!(any(target_os = "windows", target_os = "macos"));
+
 fn handle_key_modifier(io: &mut imgui::Io, key: VirtualKeyCode, down: bool) {
     if key == VirtualKeyCode::LShift || key == VirtualKeyCode::RShift {
         io.add_key_event(imgui::Key::ModShift, down);
     } else if key == VirtualKeyCode::LControl || key == VirtualKeyCode::RControl {
         io.add_key_event(imgui::Key::ModCtrl, down);
-    } else if key == VirtualKeyCode::LAlt || key == VirtualKeyCode::RAlt {
+    } else if key == VirtualKeyCode::LAlt || (key == VirtualKeyCode::RAlt && !RIGHT_ALT_IS_ALTGR) {
         io.add_key_event(imgui::Key::ModAlt, down);
     } else if key == VirtualKeyCode::LWin || key == VirtualKeyCode::RWin {
         io.add_key_event(imgui::Key::ModSuper, down);
     }
 }
 
+// Every keyboard `imgui::Key` has a winit source. The gamepad keys, the mouse
+// pseudo keys and the `Mod*`/`ReservedFor*` keys are intentionally unmapped:
+// they are fed through `handle_key_modifier`, `ModifiersChanged` and the mouse
+// events instead, or have no winit counterpart at all.
+//
+// All the keys, `KeypadEqual` included, exist since imgui 1.87 and imgui-rs 0.11
+// links 1.89, so none of them need guarding.
 fn to_imgui_key(keycode: VirtualKeyCode) -> Option<Key> {
     match keycode {
         VirtualKeyCode::Tab => Some(Key::Tab),
@@ -699,7 +3599,7 @@ fn to_imgui_key(keycode: VirtualKeyCode) -> Option<Key> {
         VirtualKeyCode::RShift => Some(Key::RightShift),
         VirtualKeyCode::RAlt => Some(Key::RightAlt),
         VirtualKeyCode::RWin => Some(Key::RightSuper),
-        //VirtualKeyCode::Menu => Some(Key::Menu), // TODO: find out if there is a Menu key in winit
+        VirtualKeyCode::Apps => Some(Key::Menu),
         VirtualKeyCode::Key0 => Some(Key::Alpha0),
         VirtualKeyCode::Key1 => Some(Key::Alpha1),
         VirtualKeyCode::Key2 => Some(Key::Alpha2),
@@ -781,23 +3681,792 @@ fn to_imgui_key(keycode: VirtualKeyCode) -> Option<Key> {
         VirtualKeyCode::NumpadAdd => Some(Key::KeypadAdd),
         VirtualKeyCode::NumpadEnter => Some(Key::KeypadEnter),
         VirtualKeyCode::NumpadEquals => Some(Key::KeypadEqual),
+        // The separator key of some keypads, e.g. the Brazilian one. imgui has no key
+        // of its own for it and it types the decimal separator there.
+        VirtualKeyCode::NumpadComma => Some(Key::KeypadDecimal),
+        _ => None,
+    }
+}
+
+// Windows reports set 1 scan codes, which match the evdev codes winit passes on for
+// X11 and Wayland in the main key block.
+#[cfg(not(target_os = "macos"))]
+fn scancode_to_imgui_key(scancode: u32) -> Option<Key> {
+    match scancode {
+        0x02 => Some(Key::Alpha1),
+        0x03 => Some(Key::Alpha2),
+        0x04 => Some(Key::Alpha3),
+        0x05 => Some(Key::Alpha4),
+        0x06 => Some(Key::Alpha5),
+        0x07 => Some(Key::Alpha6),
+        0x08 => Some(Key::Alpha7),
+        0x09 => Some(Key::Alpha8),
+        0x0A => Some(Key::Alpha9),
+        0x0B => Some(Key::Alpha0),
+        0x0C => Some(Key::Minus),
+        0x0D => Some(Key::Equal),
+        0x10 => Some(Key::Q),
+        0x11 => Some(Key::W),
+        0x12 => Some(Key::E),
+        0x13 => Some(Key::R),
+        0x14 => Some(Key::T),
+        0x15 => Some(Key::Y),
+        0x16 => Some(Key::U),
+        0x17 => Some(Key::I),
+        0x18 => Some(Key::O),
+        0x19 => Some(Key::P),
+        0x1A => Some(Key::LeftBracket),
+        0x1B => Some(Key::RightBracket),
+        0x1E => Some(Key::A),
+        0x1F => Some(Key::S),
+        0x20 => Some(Key::D),
+        0x21 => Some(Key::F),
+        0x22 => Some(Key::G),
+        0x23 => Some(Key::H),
+        0x24 => Some(Key::J),
+        0x25 => Some(Key::K),
+        0x26 => Some(Key::L),
+        0x27 => Some(Key::Semicolon),
+        0x28 => Some(Key::Apostrophe),
+        0x29 => Some(Key::GraveAccent),
+        0x2B => Some(Key::Backslash),
+        0x2C => Some(Key::Z),
+        0x2D => Some(Key::X),
+        0x2E => Some(Key::C),
+        0x2F => Some(Key::V),
+        0x30 => Some(Key::B),
+        0x31 => Some(Key::N),
+        0x32 => Some(Key::M),
+        0x33 => Some(Key::Comma),
+        0x34 => Some(Key::Period),
+        0x35 => Some(Key::Slash),
+        _ => None,
+    }
+}
+
+// macOS virtual key codes (`kVK_ANSI_*`), which are positional as well.
+#[cfg(target_os = "macos")]
+fn scancode_to_imgui_key(scancode: u32) -> Option<Key> {
+    match scancode {
+        0x00 => Some(Key::A),
+        0x01 => Some(Key::S),
+        0x02 => Some(Key::D),
+        0x03 => Some(Key::F),
+        0x04 => Some(Key::H),
+        0x05 => Some(Key::G),
+        0x06 => Some(Key::Z),
+        0x07 => Some(Key::X),
+        0x08 => Some(Key::C),
+        0x09 => Some(Key::V),
+        0x0B => Some(Key::B),
+        0x0C => Some(Key::Q),
+        0x0D => Some(Key::W),
+        0x0E => Some(Key::E),
+        0x0F => Some(Key::R),
+        0x10 => Some(Key::Y),
+        0x11 => Some(Key::T),
+        0x12 => Some(Key::Alpha1),
+        0x13 => Some(Key::Alpha2),
+        0x14 => Some(Key::Alpha3),
+        0x15 => Some(Key::Alpha4),
+        0x16 => Some(Key::Alpha6),
+        0x17 => Some(Key::Alpha5),
+        0x18 => Some(Key::Equal),
+        0x19 => Some(Key::Alpha9),
+        0x1A => Some(Key::Alpha7),
+        0x1B => Some(Key::Minus),
+        0x1C => Some(Key::Alpha8),
+        0x1D => Some(Key::Alpha0),
+        0x1E => Some(Key::RightBracket),
+        0x1F => Some(Key::O),
+        0x20 => Some(Key::U),
+        0x21 => Some(Key::LeftBracket),
+        0x22 => Some(Key::I),
+        0x23 => Some(Key::P),
+        0x25 => Some(Key::L),
+        0x26 => Some(Key::J),
+        0x27 => Some(Key::Apostrophe),
+        0x28 => Some(Key::K),
+        0x29 => Some(Key::Semicolon),
+        0x2A => Some(Key::Backslash),
+        0x2B => Some(Key::Comma),
+        0x2C => Some(Key::Slash),
+        0x2D => Some(Key::N),
+        0x2E => Some(Key::M),
+        0x2F => Some(Key::Period),
+        0x32 => Some(Key::GraveAccent),
         _ => None,
     }
 }
 
+// Only the named variants map to the primary buttons. `Other` carries the platform's
+// raw button number, which for the two side buttons differs between platforms and
+// overlaps the primary buttons' numbers on Windows. Anything else has no imgui
+// counterpart and is ignored.
 fn to_imgui_mouse_button(button: winit::event::MouseButton) -> Option<MouseButton> {
     match button {
-        winit::event::MouseButton::Left | winit::event::MouseButton::Other(0) => {
-            Some(imgui::MouseButton::Left)
+        winit::event::MouseButton::Left => Some(imgui::MouseButton::Left),
+        winit::event::MouseButton::Right => Some(imgui::MouseButton::Right),
+        winit::event::MouseButton::Middle => Some(imgui::MouseButton::Middle),
+        winit::event::MouseButton::Other(n) if SIDE_BUTTONS[0].contains(&n) => {
+            Some(imgui::MouseButton::Extra1)
+        }
+        winit::event::MouseButton::Other(n) if SIDE_BUTTONS[1].contains(&n) => {
+            Some(imgui::MouseButton::Extra2)
+        }
+        winit::event::MouseButton::Other(_) => None,
+    }
+}
+
+// XBUTTON1/XBUTTON2.
+#[cfg(target_os = "windows")]
+const SIDE_BUTTONS: [&[u16]; 2] = [&[1], &[2]];
+// NSEvent button numbers.
+#[cfg(target_os = "macos")]
+const SIDE_BUTTONS: [&[u16]; 2] = [&[3], &[4]];
+// X11 buttons 8/9 and the evdev codes BTN_SIDE/BTN_EXTRA reported on Wayland.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const SIDE_BUTTONS: [&[u16]; 2] = [&[8, 0x113], &[9, 0x114]];
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+
+    // imgui-rs allows only one active context, but tests run in parallel.
+    static CONTEXT_LOCK: Mutex<()> = Mutex::new(());
+
+    fn create_context() -> (MutexGuard<'static, ()>, imgui::Context) {
+        let guard = CONTEXT_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut imgui = imgui::Context::create();
+        imgui.set_ini_filename(None);
+        (guard, imgui)
+    }
+
+    // A context with viewports enabled and the backends installed, as `Renderer::new`
+    // leaves it, minus the windows. Returns the queue the platform backend fills.
+    fn viewport_context() -> (
+        MutexGuard<'static, ()>,
+        imgui::Context,
+        Rc<RefCell<VecDeque<ViewportEvent>>>,
+    ) {
+        let (guard, mut imgui) = create_context();
+        imgui.fonts().build_rgba32_texture();
+        imgui.io_mut().display_size = [800.0, 600.0];
+        enable_docking_and_viewports(imgui.io_mut(), true, true);
+        imgui.io_mut().backend_flags.insert(
+            imgui::BackendFlags::PLATFORM_HAS_VIEWPORTS
+                | imgui::BackendFlags::RENDERER_HAS_VIEWPORTS,
+        );
+        imgui
+            .platform_io_mut()
+            .monitors
+            .replace_from_slice(&[imgui::PlatformMonitor {
+                main_pos: [0.0, 0.0],
+                main_size: [1920.0, 1080.0],
+                work_pos: [0.0, 0.0],
+                work_size: [1920.0, 1080.0],
+                dpi_scale: 1.0,
+            }]);
+
+        let viewport = imgui.main_viewport_mut();
+        viewport.size = [800.0, 600.0];
+        viewport.platform_user_data = Box::into_raw(Box::new(ViewportData {
+            pos: [0.0, 0.0],
+            size: [800.0, 600.0],
+            focus: true,
+            minimized: false,
+            top_most: false,
+        }))
+        .cast();
+
+        let event_queue = Rc::new(RefCell::new(VecDeque::new()));
+        imgui.set_platform_backend(PlatformBackend {
+            event_queue: event_queue.clone(),
+        });
+        imgui.set_renderer_backend(RendererBackend {});
+
+        (guard, imgui, event_queue)
+    }
+
+    // Ends the frame the way `update` does, minus the windows.
+    fn end_frame(imgui: &mut imgui::Context) {
+        imgui.render();
+        imgui.update_platform_windows();
+    }
+
+    // A viewport as imgui hands it to the platform backend.
+    fn test_viewport(id: u32) -> Box<imgui::sys::ImGuiViewport> {
+        let mut raw: Box<imgui::sys::ImGuiViewport> = Box::new(unsafe { std::mem::zeroed() });
+        raw.ID = id;
+        raw.Pos = imgui::sys::ImVec2::new(10.0, 20.0);
+        raw.Size = imgui::sys::ImVec2::new(300.0, 200.0);
+        raw
+    }
+
+    fn as_viewport(raw: &mut imgui::sys::ImGuiViewport) -> &mut imgui::Viewport {
+        unsafe { &mut *(raw as *mut imgui::sys::ImGuiViewport).cast::<imgui::Viewport>() }
+    }
+
+    // Every key `to_imgui_key` is expected to handle.
+    const MAPPED_KEYCODES: &[VirtualKeyCode] = &[
+        VirtualKeyCode::Tab,
+        VirtualKeyCode::Left,
+        VirtualKeyCode::Right,
+        VirtualKeyCode::Up,
+        VirtualKeyCode::Down,
+        VirtualKeyCode::PageUp,
+        VirtualKeyCode::PageDown,
+        VirtualKeyCode::Home,
+        VirtualKeyCode::End,
+        VirtualKeyCode::Insert,
+        VirtualKeyCode::Delete,
+        VirtualKeyCode::Back,
+        VirtualKeyCode::Space,
+        VirtualKeyCode::Return,
+        VirtualKeyCode::Escape,
+        VirtualKeyCode::LControl,
+        VirtualKeyCode::LShift,
+        VirtualKeyCode::LAlt,
+        VirtualKeyCode::LWin,
+        VirtualKeyCode::RControl,
+        VirtualKeyCode::RShift,
+        VirtualKeyCode::RAlt,
+        VirtualKeyCode::RWin,
+        VirtualKeyCode::Apps,
+        VirtualKeyCode::Key0,
+        VirtualKeyCode::Key1,
+        VirtualKeyCode::Key2,
+        VirtualKeyCode::Key3,
+        VirtualKeyCode::Key4,
+        VirtualKeyCode::Key5,
+        VirtualKeyCode::Key6,
+        VirtualKeyCode::Key7,
+        VirtualKeyCode::Key8,
+        VirtualKeyCode::Key9,
+        VirtualKeyCode::A,
+        VirtualKeyCode::B,
+        VirtualKeyCode::C,
+        VirtualKeyCode::D,
+        VirtualKeyCode::E,
+        VirtualKeyCode::F,
+        VirtualKeyCode::G,
+        VirtualKeyCode::H,
+        VirtualKeyCode::I,
+        VirtualKeyCode::J,
+        VirtualKeyCode::K,
+        VirtualKeyCode::L,
+        VirtualKeyCode::M,
+        VirtualKeyCode::N,
+        VirtualKeyCode::O,
+        VirtualKeyCode::P,
+        VirtualKeyCode::Q,
+        VirtualKeyCode::R,
+        VirtualKeyCode::S,
+        VirtualKeyCode::T,
+        VirtualKeyCode::U,
+        VirtualKeyCode::V,
+        VirtualKeyCode::W,
+        VirtualKeyCode::X,
+        VirtualKeyCode::Y,
+        VirtualKeyCode::Z,
+        VirtualKeyCode::F1,
+        VirtualKeyCode::F2,
+        VirtualKeyCode::F3,
+        VirtualKeyCode::F4,
+        VirtualKeyCode::F5,
+        VirtualKeyCode::F6,
+        VirtualKeyCode::F7,
+        VirtualKeyCode::F8,
+        VirtualKeyCode::F9,
+        VirtualKeyCode::F10,
+        VirtualKeyCode::F11,
+        VirtualKeyCode::F12,
+        VirtualKeyCode::Apostrophe,
+        VirtualKeyCode::Comma,
+        VirtualKeyCode::Minus,
+        VirtualKeyCode::Period,
+        VirtualKeyCode::Slash,
+        VirtualKeyCode::Semicolon,
+        VirtualKeyCode::Equals,
+        VirtualKeyCode::LBracket,
+        VirtualKeyCode::Backslash,
+        VirtualKeyCode::RBracket,
+        VirtualKeyCode::Grave,
+        VirtualKeyCode::Capital,
+        VirtualKeyCode::Scroll,
+        VirtualKeyCode::Numlock,
+        VirtualKeyCode::Snapshot,
+        VirtualKeyCode::Pause,
+        VirtualKeyCode::Numpad0,
+        VirtualKeyCode::Numpad1,
+        VirtualKeyCode::Numpad2,
+        VirtualKeyCode::Numpad3,
+        VirtualKeyCode::Numpad4,
+        VirtualKeyCode::Numpad5,
+        VirtualKeyCode::Numpad6,
+        VirtualKeyCode::Numpad7,
+        VirtualKeyCode::Numpad8,
+        VirtualKeyCode::Numpad9,
+        VirtualKeyCode::NumpadDecimal,
+        VirtualKeyCode::NumpadDivide,
+        VirtualKeyCode::NumpadMultiply,
+        VirtualKeyCode::NumpadSubtract,
+        VirtualKeyCode::NumpadAdd,
+        VirtualKeyCode::NumpadEnter,
+        VirtualKeyCode::NumpadEquals,
+        VirtualKeyCode::NumpadComma,
+    ];
+
+    // imgui keys that winit's keyboard events can't produce: gamepad, mouse and
+    // modifier state arrive through other events, the reserved keys are internal.
+    const UNMAPPED_KEYS: &[&str] = &[
+        "GamepadStart",
+        "GamepadBack",
+        "GamepadFaceLeft",
+        "GamepadFaceRight",
+        "GamepadFaceUp",
+        "GamepadFaceDown",
+        "GamepadDpadLeft",
+        "GamepadDpadRight",
+        "GamepadDpadUp",
+        "GamepadDpadDown",
+        "GamepadL1",
+        "GamepadR1",
+        "GamepadL2",
+        "GamepadR2",
+        "GamepadL3",
+        "GamepadR3",
+        "GamepadLStickLeft",
+        "GamepadLStickRight",
+        "GamepadLStickUp",
+        "GamepadLStickDown",
+        "GamepadRStickLeft",
+        "GamepadRStickRight",
+        "GamepadRStickUp",
+        "GamepadRStickDown",
+        "MouseLeft",
+        "MouseRight",
+        "MouseMiddle",
+        "MouseX1",
+        "MouseX2",
+        "MouseWheelX",
+        "MouseWheelY",
+        "ModCtrl",
+        "ModShift",
+        "ModAlt",
+        "ModSuper",
+    ];
+
+    #[test]
+    fn every_keyboard_key_has_a_winit_mapping() {
+        let mapped: Vec<Key> = MAPPED_KEYCODES
+            .iter()
+            .map(|&keycode| {
+                to_imgui_key(keycode).unwrap_or_else(|| panic!("{keycode:?} is not mapped"))
+            })
+            .collect();
+
+        for key in Key::VARIANTS {
+            let name = format!("{key:?}");
+            let unmapped = name.starts_with("ReservedFor") || UNMAPPED_KEYS.contains(&&*name);
+            assert_eq!(
+                mapped.contains(&key),
+                !unmapped,
+                "{name} is {}",
+                if unmapped {
+                    "mapped but listed as unmapped"
+                } else {
+                    "not mapped"
+                }
+            );
         }
-        winit::event::MouseButton::Right | winit::event::MouseButton::Other(1) => {
-            Some(imgui::MouseButton::Right)
+    }
+
+    #[test]
+    fn side_buttons_map_to_the_extra_buttons() {
+        for (buttons, extra) in SIDE_BUTTONS
+            .into_iter()
+            .zip([MouseButton::Extra1, MouseButton::Extra2])
+        {
+            for &n in buttons {
+                assert_eq!(
+                    to_imgui_mouse_button(winit::event::MouseButton::Other(n)),
+                    Some(extra)
+                );
+            }
         }
-        winit::event::MouseButton::Middle | winit::event::MouseButton::Other(2) => {
-            Some(imgui::MouseButton::Middle)
+    }
+
+    #[test]
+    fn raw_primary_button_numbers_are_ignored() {
+        assert_eq!(
+            to_imgui_mouse_button(winit::event::MouseButton::Left),
+            Some(MouseButton::Left)
+        );
+        assert_eq!(
+            to_imgui_mouse_button(winit::event::MouseButton::Right),
+            Some(MouseButton::Right)
+        );
+        assert_eq!(
+            to_imgui_mouse_button(winit::event::MouseButton::Middle),
+            Some(MouseButton::Middle)
+        );
+
+        // On Windows 1 and 2 are the side buttons, everywhere else these numbers must
+        // not turn into a second left, right or middle click.
+        for n in 0..=2 {
+            if !SIDE_BUTTONS.concat().contains(&n) {
+                assert_eq!(
+                    to_imgui_mouse_button(winit::event::MouseButton::Other(n)),
+                    None
+                );
+            }
         }
-        winit::event::MouseButton::Other(3) => Some(imgui::MouseButton::Extra1),
-        winit::event::MouseButton::Other(4) => Some(imgui::MouseButton::Extra2),
-        _ => None,
+    }
+
+    #[test]
+    fn altgr_types_without_the_alt_modifier() {
+        let (_guard, mut imgui) = create_context();
+        imgui.fonts().build_rgba32_texture();
+        imgui.io_mut().display_size = [800.0, 600.0];
+
+        // `@` on a German layout: AltGr + Q.
+        handle_key_modifier(imgui.io_mut(), VirtualKeyCode::RAlt, true);
+        imgui.io_mut().add_key_event(Key::RightAlt, true);
+        imgui.io_mut().add_input_character('@');
+        let ui = imgui.new_frame();
+        assert_eq!(ui.io().key_alt, !RIGHT_ALT_IS_ALTGR);
+        assert!(ui.is_key_down(Key::RightAlt));
+        assert_eq!(
+            ui.io().input_queue_characters().collect::<Vec<_>>(),
+            vec!['@']
+        );
+        imgui.render();
+    }
+
+    #[test]
+    fn srgb_clear_color_is_linearized() {
+        let color = srgb_to_linear(wgpu::Color {
+            r: 0.0,
+            g: 0.5,
+            b: 1.0,
+            a: 0.5,
+        });
+
+        assert_eq!(color.r, 0.0);
+        assert!((color.g - 0.2140).abs() < 1e-4, "{}", color.g);
+        assert!((color.b - 1.0).abs() < 1e-12, "{}", color.b);
+        // Alpha is linear already.
+        assert_eq!(color.a, 0.5);
+    }
+
+    #[test]
+    fn backend_names_are_distinct_and_versioned() {
+        let (platform, renderer) = backend_names();
+        let version = env!("CARGO_PKG_VERSION");
+        assert_ne!(platform, renderer);
+        assert!(platform.ends_with(version), "{}", platform);
+        assert!(renderer.ends_with(version), "{}", renderer);
+    }
+
+    #[test]
+    fn refresh_rate_prefers_the_current_resolution() {
+        let modes = [
+            (PhysicalSize::new(1280, 720), 240_000),
+            (PhysicalSize::new(1920, 1080), 60_000),
+            (PhysicalSize::new(1920, 1080), 144_000),
+        ];
+        assert_eq!(
+            pick_refresh_rate(PhysicalSize::new(1920, 1080), &modes),
+            Some(144_000)
+        );
+    }
+
+    #[test]
+    fn refresh_rate_falls_back_to_any_mode() {
+        let modes = [
+            (PhysicalSize::new(1280, 720), 75_000),
+            (PhysicalSize::new(1024, 768), 60_000),
+        ];
+        assert_eq!(
+            pick_refresh_rate(PhysicalSize::new(1920, 1080), &modes),
+            Some(75_000)
+        );
+        assert_eq!(pick_refresh_rate(PhysicalSize::new(1920, 1080), &[]), None);
+    }
+
+    #[test]
+    fn supersampling_scales_the_target() {
+        assert_eq!(supersampled_size([800, 600], 2.0), [1600, 1200]);
+        assert_eq!(supersampled_size([801, 601], 1.5), [1202, 902]);
+        assert_eq!(supersampled_size([800, 600], 1.0), [800, 600]);
+    }
+
+    #[test]
+    fn work_area_excludes_the_insets() {
+        assert_eq!(
+            work_area([100.0, 50.0], [800.0, 600.0], [10.0, 20.0, 30.0, 40.0]),
+            ([110.0, 70.0], [760.0, 540.0])
+        );
+    }
+
+    #[test]
+    fn work_area_never_gets_a_negative_size() {
+        assert_eq!(
+            work_area([0.0, 0.0], [30.0, 30.0], [20.0, 25.0, 20.0, 25.0]),
+            ([20.0, 25.0], [0.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn dpi_scaling_flags_follow_the_arguments() {
+        let (_guard, mut imgui) = create_context();
+
+        enable_dpi_scaling(imgui.io_mut(), true, false);
+        assert!(imgui.io().config_flags.contains(DPI_ENABLE_SCALE_FONTS));
+        assert!(!imgui.io().config_flags.contains(DPI_ENABLE_SCALE_VIEWPORTS));
+
+        enable_dpi_scaling(imgui.io_mut(), false, true);
+        assert!(!imgui.io().config_flags.contains(DPI_ENABLE_SCALE_FONTS));
+        assert!(imgui.io().config_flags.contains(DPI_ENABLE_SCALE_VIEWPORTS));
+    }
+
+    #[test]
+    fn docking_and_viewports_can_be_toggled() {
+        let (_guard, mut imgui) = create_context();
+
+        enable_docking_and_viewports(imgui.io_mut(), true, true);
+        assert!(imgui
+            .io()
+            .config_flags
+            .contains(ConfigFlags::DOCKING_ENABLE));
+        assert!(imgui
+            .io()
+            .config_flags
+            .contains(ConfigFlags::VIEWPORTS_ENABLE));
+
+        enable_docking_and_viewports(imgui.io_mut(), true, false);
+        assert!(imgui
+            .io()
+            .config_flags
+            .contains(ConfigFlags::DOCKING_ENABLE));
+        assert!(!imgui
+            .io()
+            .config_flags
+            .contains(ConfigFlags::VIEWPORTS_ENABLE));
+
+        enable_docking_and_viewports(imgui.io_mut(), false, false);
+        assert!(!imgui
+            .io()
+            .config_flags
+            .contains(ConfigFlags::DOCKING_ENABLE));
+    }
+
+    #[test]
+    fn viewport_created_and_destroyed_in_one_frame() {
+        let (_guard, imgui) = create_context();
+        let event_queue = Rc::new(RefCell::new(VecDeque::new()));
+        let mut backend = PlatformBackend {
+            event_queue: event_queue.clone(),
+        };
+
+        let mut raw = test_viewport(42);
+        let viewport = as_viewport(&mut raw);
+        imgui::PlatformViewportBackend::create_window(&mut backend, viewport);
+        let data = unsafe { viewport.platform_user_data.cast::<ViewportData>().as_ref() };
+        assert_eq!(
+            data.map(|data| (data.pos, data.size)),
+            Some(([10.0, 20.0], [300.0, 200.0]))
+        );
+
+        imgui::PlatformViewportBackend::destroy_window(&mut backend, viewport);
+        assert!(viewport.platform_user_data.is_null());
+
+        let events: Vec<_> = event_queue.borrow_mut().drain(..).collect();
+        assert!(
+            matches!(
+                events[..],
+                [ViewportEvent::Create(created), ViewportEvent::Destroy(destroyed)]
+                    if created == viewport.id && destroyed == viewport.id
+            ),
+            "{events:?}"
+        );
+
+        // By the time the events are processed imgui already dropped the viewport, so
+        // `Create` finds nothing to open a window for.
+        assert!(imgui.viewport_by_id(viewport.id).is_none());
+    }
+
+    #[test]
+    fn backends_only_queue_their_own_viewports() {
+        let queues = [(); 2].map(|_| Rc::new(RefCell::new(VecDeque::new())));
+        let mut backends = queues
+            .clone()
+            .map(|event_queue| PlatformBackend { event_queue });
+
+        let mut raw = test_viewport(7);
+        let viewport = as_viewport(&mut raw);
+        imgui::PlatformViewportBackend::create_window(&mut backends[0], viewport);
+        imgui::PlatformViewportBackend::set_window_title(&mut backends[0], viewport, "first");
+        assert_eq!(queues[0].borrow().len(), 2);
+        assert!(queues[1].borrow().is_empty());
+
+        imgui::PlatformViewportBackend::destroy_window(&mut backends[0], viewport);
+        assert_eq!(queues[0].borrow().len(), 3);
+        assert!(queues[1].borrow().is_empty());
+    }
+
+    #[test]
+    fn linear_targets_get_the_srgb_shader() {
+        assert_eq!(
+            default_renderer_config(TextureFormat::Bgra8Unorm).fragment_shader_entry_point,
+            RendererConfig::new_srgb().fragment_shader_entry_point
+        );
+        assert_eq!(
+            default_renderer_config(TextureFormat::Bgra8UnormSrgb).fragment_shader_entry_point,
+            RendererConfig::new().fragment_shader_entry_point
+        );
+    }
+
+    #[test]
+    fn no_mouse_pos_is_not_a_valid_position() {
+        let (_guard, mut imgui) = create_context();
+        imgui.fonts().build_rgba32_texture();
+        imgui.io_mut().display_size = [800.0, 600.0];
+
+        imgui.io_mut().add_mouse_pos_event([0.0, 0.0]);
+        imgui.io_mut().add_mouse_pos_event(NO_MOUSE_POS);
+        let ui = imgui.new_frame();
+        assert_eq!(ui.io().mouse_pos, NO_MOUSE_POS);
+        assert!(!ui.is_current_mouse_pos_valid());
+        imgui.render();
+    }
+
+    #[test]
+    fn viewports_without_draw_data_are_skipped() {
+        let mut raw = test_viewport(7);
+        assert!(viewport_draw_data(as_viewport(&mut raw)).is_none());
+
+        let (_guard, mut imgui, _) = viewport_context();
+        assert!(viewport_draw_data(imgui.main_viewport()).is_none());
+
+        imgui.new_frame();
+        end_frame(&mut imgui);
+        assert!(viewport_draw_data(imgui.main_viewport()).is_some());
+    }
+
+    #[test]
+    fn detached_window_gets_a_viewport_of_its_own() {
+        let (_guard, mut imgui, event_queue) = viewport_context();
+        let main_id = imgui.main_viewport().id;
+
+        let ui = imgui.new_frame();
+        let (id, built) =
+            detached_window(ui, "Detached", [900.0, 100.0], [200.0, 150.0], || 42).unwrap();
+        assert_eq!(built, 42);
+        assert_ne!(id, main_id);
+        end_frame(&mut imgui);
+
+        let events: Vec<_> = event_queue.borrow_mut().drain(..).collect();
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, ViewportEvent::Create(created) if *created == id)),
+            "{events:?}"
+        );
+        assert_eq!(
+            imgui.viewport_by_id(id).map(|viewport| viewport.pos),
+            Some([900.0, 100.0])
+        );
+    }
+
+    #[test]
+    fn zero_sized_resize_keeps_the_display_size() {
+        let (_guard, mut imgui) = create_context();
+        imgui.io_mut().display_size = [800.0, 600.0];
+
+        apply_display_size(imgui.io_mut(), PhysicalSize::new(0, 0));
+        assert_eq!(imgui.io().display_size, [800.0, 600.0]);
+        apply_display_size(imgui.io_mut(), PhysicalSize::new(1024, 0));
+        assert_eq!(imgui.io().display_size, [800.0, 600.0]);
+
+        apply_display_size(imgui.io_mut(), PhysicalSize::new(1024, 768));
+        assert_eq!(imgui.io().display_size, [1024.0, 768.0]);
+    }
+
+    #[test]
+    fn viewport_lifecycle_through_imgui() {
+        let (_guard, mut imgui, event_queue) = viewport_context();
+        let viewport_data = |imgui: &imgui::Context, id| {
+            let viewport = imgui.viewport_by_id(id).unwrap();
+            unsafe { viewport.platform_user_data.cast::<ViewportData>().as_ref() }
+                .map(|data| (data.pos, data.size))
+        };
+
+        let ui = imgui.new_frame();
+        let (id, ()) = detached_window(ui, "Tool", [900.0, 100.0], [200.0, 150.0], || ()).unwrap();
+        end_frame(&mut imgui);
+
+        let events: Vec<_> = event_queue.borrow_mut().drain(..).collect();
+        let create = events
+            .iter()
+            .position(|event| matches!(event, ViewportEvent::Create(created) if *created == id));
+        let show = events
+            .iter()
+            .position(|event| matches!(event, ViewportEvent::SetVisible(shown) if *shown == id));
+        assert!(
+            matches!((create, show), (Some(create), Some(show)) if create < show),
+            "{events:?}"
+        );
+        assert_eq!(
+            viewport_data(&imgui, id),
+            Some(([900.0, 100.0], [200.0, 150.0]))
+        );
+
+        let ui = imgui.new_frame();
+        detach_next_window(ui, [900.0, 100.0], [200.0, 150.0]);
+        ui.window("Tool")
+            .position([1000.0, 200.0], imgui::Condition::Always)
+            .size([300.0, 250.0], imgui::Condition::Always)
+            .build(|| ());
+        end_frame(&mut imgui);
+
+        let events: Vec<_> = event_queue.borrow_mut().drain(..).collect();
+        assert!(
+            events.iter().any(|event| matches!(
+                event,
+                ViewportEvent::SetPos(moved, [1000.0, 200.0]) if *moved == id
+            )),
+            "{events:?}"
+        );
+        assert!(
+            events.iter().any(|event| matches!(
+                event,
+                ViewportEvent::SetSize(resized, [300.0, 250.0]) if *resized == id
+            )),
+            "{events:?}"
+        );
+        assert_eq!(
+            viewport_data(&imgui, id),
+            Some(([1000.0, 200.0], [300.0, 250.0]))
+        );
+
+        // Not begun anymore, imgui closes the platform window right away and drops the
+        // viewport itself a few frames later.
+        imgui.new_frame();
+        end_frame(&mut imgui);
+
+        let events: Vec<_> = event_queue.borrow_mut().drain(..).collect();
+        assert!(
+            events.iter().any(
+                |event| matches!(event, ViewportEvent::Destroy(destroyed) if *destroyed == id)
+            ),
+            "{events:?}"
+        );
+        assert_eq!(viewport_data(&imgui, id), None);
     }
 }
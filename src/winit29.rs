@@ -0,0 +1,249 @@
+//! Input handling for applications on winit 0.29, enabled by the `winit_0_29` feature.
+//!
+//! `Renderer` creates its viewport windows with winit 0.27, so with a winit 0.29 event
+//! loop only the main window is available: leave viewports disabled, feed the events of
+//! the window through `InputState::handle_event` and draw with `Renderer::render`.
+
+use imgui::{internal::RawCast, Io, Key, MouseButton};
+use winit_0_29::event::{ElementState, Ime, KeyEvent, MouseScrollDelta, WindowEvent};
+use winit_0_29::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+
+use crate::{DEFAULT_PIXELS_PER_LINE, NO_MOUSE_POS};
+
+/// Translates winit 0.29 window events into imgui input.
+///
+/// Keys are mapped by their physical position, so shortcuts follow the US layout like
+/// `Renderer::set_physical_keys(true)`. Text arrives separately and follows the layout.
+pub struct InputState {
+    pixels_per_line: f32,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            pixels_per_line: DEFAULT_PIXELS_PER_LINE,
+        }
+    }
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many pixels of a pixel based scroll make one line.
+    pub fn set_pixels_per_line(&mut self, pixels_per_line: f32) {
+        self.pixels_per_line = pixels_per_line;
+    }
+
+    /// Feeds a window event into imgui. Returns whether imgui wants the input for itself,
+    /// in which case the application should ignore it.
+    pub fn handle_event(&mut self, io: &mut Io, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::Resized(size) if size.width != 0 && size.height != 0 => {
+                io.display_size = [size.width as f32, size.height as f32];
+                false
+            }
+            WindowEvent::Focused(focused) => {
+                unsafe {
+                    imgui::sys::ImGuiIO_AddFocusEvent(io.raw_mut(), *focused);
+                }
+                false
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                handle_key_event(io, event);
+                io.want_capture_keyboard
+            }
+            WindowEvent::Ime(Ime::Commit(text)) => {
+                add_text(io, text);
+                io.want_capture_keyboard
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                handle_modifiers(io, modifiers.state());
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                io.add_mouse_pos_event([position.x as f32, position.y as f32]);
+                io.want_capture_mouse
+            }
+            WindowEvent::CursorLeft { .. } => {
+                io.add_mouse_pos_event(NO_MOUSE_POS);
+                false
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let wheel = match *delta {
+                    MouseScrollDelta::LineDelta(h, v) => [h, v],
+                    MouseScrollDelta::PixelDelta(pos) => [
+                        pos.x as f32 / self.pixels_per_line,
+                        pos.y as f32 / self.pixels_per_line,
+                    ],
+                };
+
+                if wheel != [0.0, 0.0] {
+                    io.add_mouse_wheel_event(wheel);
+                }
+                io.want_capture_mouse
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                if let Some(button) = to_imgui_mouse_button(*button) {
+                    io.add_mouse_button_event(button, *state == ElementState::Pressed);
+                }
+                io.want_capture_mouse
+            }
+            _ => false,
+        }
+    }
+}
+
+fn handle_key_event(io: &mut Io, event: &KeyEvent) {
+    let pressed = event.state == ElementState::Pressed;
+
+    if let PhysicalKey::Code(code) = event.physical_key {
+        if let Some(key) = to_imgui_key(code) {
+            io.add_key_event(key, pressed);
+        }
+    }
+
+    if pressed {
+        if let Some(text) = &event.text {
+            add_text(io, text);
+        }
+    }
+}
+
+fn add_text(io: &mut Io, text: &str) {
+    // Enter, tab and backspace come with text too, imgui handles them as keys.
+    for c in text.chars().filter(|c| !c.is_control()) {
+        io.add_input_character(c);
+    }
+}
+
+fn handle_modifiers(io: &mut Io, modifiers: ModifiersState) {
+    io.add_key_event(Key::ModShift, modifiers.shift_key());
+    io.add_key_event(Key::ModCtrl, modifiers.control_key());
+    io.add_key_event(Key::ModAlt, modifiers.alt_key());
+    io.add_key_event(Key::ModSuper, modifiers.super_key());
+}
+
+fn to_imgui_mouse_button(button: winit_0_29::event::MouseButton) -> Option<MouseButton> {
+    match button {
+        winit_0_29::event::MouseButton::Left => Some(MouseButton::Left),
+        winit_0_29::event::MouseButton::Right => Some(MouseButton::Right),
+        winit_0_29::event::MouseButton::Middle => Some(MouseButton::Middle),
+        winit_0_29::event::MouseButton::Back => Some(MouseButton::Extra1),
+        winit_0_29::event::MouseButton::Forward => Some(MouseButton::Extra2),
+        winit_0_29::event::MouseButton::Other(_) => None,
+    }
+}
+
+fn to_imgui_key(code: KeyCode) -> Option<Key> {
+    match code {
+        KeyCode::Tab => Some(Key::Tab),
+        KeyCode::ArrowLeft => Some(Key::LeftArrow),
+        KeyCode::ArrowRight => Some(Key::RightArrow),
+        KeyCode::ArrowUp => Some(Key::UpArrow),
+        KeyCode::ArrowDown => Some(Key::DownArrow),
+        KeyCode::PageUp => Some(Key::PageUp),
+        KeyCode::PageDown => Some(Key::PageDown),
+        KeyCode::Home => Some(Key::Home),
+        KeyCode::End => Some(Key::End),
+        KeyCode::Insert => Some(Key::Insert),
+        KeyCode::Delete => Some(Key::Delete),
+        KeyCode::Backspace => Some(Key::Backspace),
+        KeyCode::Space => Some(Key::Space),
+        KeyCode::Enter => Some(Key::Enter),
+        KeyCode::Escape => Some(Key::Escape),
+        KeyCode::ControlLeft => Some(Key::LeftCtrl),
+        KeyCode::ShiftLeft => Some(Key::LeftShift),
+        KeyCode::AltLeft => Some(Key::LeftAlt),
+        KeyCode::SuperLeft => Some(Key::LeftSuper),
+        KeyCode::ControlRight => Some(Key::RightCtrl),
+        KeyCode::ShiftRight => Some(Key::RightShift),
+        KeyCode::AltRight => Some(Key::RightAlt),
+        KeyCode::SuperRight => Some(Key::RightSuper),
+        KeyCode::ContextMenu => Some(Key::Menu),
+        KeyCode::Digit0 => Some(Key::Alpha0),
+        KeyCode::Digit1 => Some(Key::Alpha1),
+        KeyCode::Digit2 => Some(Key::Alpha2),
+        KeyCode::Digit3 => Some(Key::Alpha3),
+        KeyCode::Digit4 => Some(Key::Alpha4),
+        KeyCode::Digit5 => Some(Key::Alpha5),
+        KeyCode::Digit6 => Some(Key::Alpha6),
+        KeyCode::Digit7 => Some(Key::Alpha7),
+        KeyCode::Digit8 => Some(Key::Alpha8),
+        KeyCode::Digit9 => Some(Key::Alpha9),
+        KeyCode::KeyA => Some(Key::A),
+        KeyCode::KeyB => Some(Key::B),
+        KeyCode::KeyC => Some(Key::C),
+        KeyCode::KeyD => Some(Key::D),
+        KeyCode::KeyE => Some(Key::E),
+        KeyCode::KeyF => Some(Key::F),
+        KeyCode::KeyG => Some(Key::G),
+        KeyCode::KeyH => Some(Key::H),
+        KeyCode::KeyI => Some(Key::I),
+        KeyCode::KeyJ => Some(Key::J),
+        KeyCode::KeyK => Some(Key::K),
+        KeyCode::KeyL => Some(Key::L),
+        KeyCode::KeyM => Some(Key::M),
+        KeyCode::KeyN => Some(Key::N),
+        KeyCode::KeyO => Some(Key::O),
+        KeyCode::KeyP => Some(Key::P),
+        KeyCode::KeyQ => Some(Key::Q),
+        KeyCode::KeyR => Some(Key::R),
+        KeyCode::KeyS => Some(Key::S),
+        KeyCode::KeyT => Some(Key::T),
+        KeyCode::KeyU => Some(Key::U),
+        KeyCode::KeyV => Some(Key::V),
+        KeyCode::KeyW => Some(Key::W),
+        KeyCode::KeyX => Some(Key::X),
+        KeyCode::KeyY => Some(Key::Y),
+        KeyCode::KeyZ => Some(Key::Z),
+        KeyCode::F1 => Some(Key::F1),
+        KeyCode::F2 => Some(Key::F2),
+        KeyCode::F3 => Some(Key::F3),
+        KeyCode::F4 => Some(Key::F4),
+        KeyCode::F5 => Some(Key::F5),
+        KeyCode::F6 => Some(Key::F6),
+        KeyCode::F7 => Some(Key::F7),
+        KeyCode::F8 => Some(Key::F8),
+        KeyCode::F9 => Some(Key::F9),
+        KeyCode::F10 => Some(Key::F10),
+        KeyCode::F11 => Some(Key::F11),
+        KeyCode::F12 => Some(Key::F12),
+        KeyCode::Quote => Some(Key::Apostrophe),
+        KeyCode::Comma => Some(Key::Comma),
+        KeyCode::Minus => Some(Key::Minus),
+        KeyCode::Period => Some(Key::Period),
+        KeyCode::Slash => Some(Key::Slash),
+        KeyCode::Semicolon => Some(Key::Semicolon),
+        KeyCode::Equal => Some(Key::Equal),
+        KeyCode::BracketLeft => Some(Key::LeftBracket),
+        KeyCode::Backslash => Some(Key::Backslash),
+        KeyCode::BracketRight => Some(Key::RightBracket),
+        KeyCode::Backquote => Some(Key::GraveAccent),
+        KeyCode::CapsLock => Some(Key::CapsLock),
+        KeyCode::ScrollLock => Some(Key::ScrollLock),
+        KeyCode::NumLock => Some(Key::NumLock),
+        KeyCode::PrintScreen => Some(Key::PrintScreen),
+        KeyCode::Pause => Some(Key::Pause),
+        KeyCode::Numpad0 => Some(Key::Keypad0),
+        KeyCode::Numpad1 => Some(Key::Keypad1),
+        KeyCode::Numpad2 => Some(Key::Keypad2),
+        KeyCode::Numpad3 => Some(Key::Keypad3),
+        KeyCode::Numpad4 => Some(Key::Keypad4),
+        KeyCode::Numpad5 => Some(Key::Keypad5),
+        KeyCode::Numpad6 => Some(Key::Keypad6),
+        KeyCode::Numpad7 => Some(Key::Keypad7),
+        KeyCode::Numpad8 => Some(Key::Keypad8),
+        KeyCode::Numpad9 => Some(Key::Keypad9),
+        KeyCode::NumpadDecimal => Some(Key::KeypadDecimal),
+        KeyCode::NumpadDivide => Some(Key::KeypadDivide),
+        KeyCode::NumpadMultiply => Some(Key::KeypadMultiply),
+        KeyCode::NumpadSubtract => Some(Key::KeypadSubtract),
+        KeyCode::NumpadAdd => Some(Key::KeypadAdd),
+        KeyCode::NumpadEnter => Some(Key::KeypadEnter),
+        KeyCode::NumpadEqual => Some(Key::KeypadEqual),
+        KeyCode::NumpadComma => Some(Key::KeypadDecimal),
+        _ => None,
+    }
+}